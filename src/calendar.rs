@@ -0,0 +1,212 @@
+use std::{collections::BTreeMap, fs::File, io::BufReader, path::Path};
+
+use anyhow::bail;
+use chrono::{Datelike, Days, NaiveDate, Weekday};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    rules::Aliases,
+    school::{Day, Laboratory, School, Slot},
+    solver::solution::Solution,
+};
+
+/// The dates over which the weekly schedule is held
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Calendar {
+    /// First day of classes, in the first week of the cycle
+    pub start: NaiveDate,
+    /// Last day of classes
+    pub end: NaiveDate,
+    /// Days without classes in the whole school
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub holidays: Vec<Closure>,
+    /// Days each lab can't be used, by lab name
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub closures: BTreeMap<String, Vec<Closure>>,
+    /// Minutes in a period of the school timetable
+    #[serde(default = "fifty")]
+    pub period: u32,
+}
+
+fn fifty() -> u32 {
+    50
+}
+
+/// A day or a range of days off
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Closure {
+    pub from: NaiveDate,
+    /// Last day off, the same as `from` when missing
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub to: Option<NaiveDate>,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub reason: String,
+}
+
+/// A session of the schedule on one date
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Booking {
+    pub date: NaiveDate,
+    pub slot: usize,
+    pub lab: usize,
+    pub class: usize,
+    /// Why the lab is closed on the date, when it is
+    pub closed: Option<String>,
+}
+
+impl Closure {
+    pub fn contains(&self, date: NaiveDate) -> bool {
+        self.from <= date && date <= self.to.unwrap_or(self.from)
+    }
+}
+
+pub fn weekday(day: Day) -> Weekday {
+    match day {
+        Day::Monday => Weekday::Mon,
+        Day::Tuesday => Weekday::Tue,
+        Day::Wednesday => Weekday::Wed,
+        Day::Thursday => Weekday::Thu,
+        Day::Friday => Weekday::Fri,
+    }
+}
+
+impl Calendar {
+    /// Every date of the term, first to last
+    pub fn dates(&self) -> impl Iterator<Item = NaiveDate> + use<'_> {
+        self.start.iter_days().take_while(|date| *date <= self.end)
+    }
+
+    /// The week of the cycle the date is in, counting calendar weeks from the one the term
+    /// starts in, holidays included
+    pub fn week_of(&self, date: NaiveDate, weeks: usize) -> usize {
+        let monday = |d: NaiveDate| d - Days::new(d.weekday().num_days_from_monday().into());
+        let elapsed = (monday(date) - monday(self.start)).num_weeks();
+        elapsed.rem_euclid(weeks.max(1) as i64) as usize
+    }
+
+    pub fn holiday(&self, date: NaiveDate) -> Option<&Closure> {
+        self.holidays.iter().find(|h| h.contains(date))
+    }
+
+    /// Why the lab can't be used on the date, when it can't
+    pub fn closure(
+        &self,
+        school: &School,
+        aliases: &Aliases,
+        lab: usize,
+        date: NaiveDate,
+    ) -> Option<&Closure> {
+        let lab: &Laboratory = school.get(lab);
+        (self.closures.iter())
+            .filter(|(name, _)| aliases.lab(name) == lab.name)
+            .flat_map(|(_, closures)| closures)
+            .find(|c| c.contains(date))
+    }
+
+    /// Names of closed labs that are not in the school
+    pub fn unknown_labs(&self, school: &School, aliases: &Aliases) -> Vec<&str> {
+        (self.closures.keys())
+            .filter(|name| {
+                let lab = Laboratory {
+                    name: aliases.lab(name),
+                };
+                school.labs.find_key(&lab).is_none()
+            })
+            .map(|name| name.as_str())
+            .collect()
+    }
+
+    /// Every session of the solution on the dates of the term it is held, in a cycle of
+    /// `weeks` weeks. Holidays have no sessions, while the ones in closed labs are kept and
+    /// marked, to be moved by hand.
+    pub fn bookings(
+        &self,
+        school: &School,
+        aliases: &Aliases,
+        solution: &Solution,
+        weeks: usize,
+    ) -> Vec<Booking> {
+        let mut by_day: BTreeMap<(u32, usize), Vec<_>> = BTreeMap::new();
+        for slotted in &solution.slotted {
+            let slot: &Slot = school.get(slotted.slot);
+            let key = (weekday(slot.day).num_days_from_monday(), slotted.week);
+            by_day.entry(key).or_default().push(slotted);
+        }
+        let mut bookings = vec![];
+        for date in self.dates() {
+            if self.holiday(date).is_some() {
+                continue;
+            }
+            let key = (
+                date.weekday().num_days_from_monday(),
+                self.week_of(date, weeks),
+            );
+            for slotted in by_day.get(&key).into_iter().flatten() {
+                let closure = self.closure(school, aliases, slotted.lab, date);
+                bookings.push(Booking {
+                    date,
+                    slot: slotted.slot,
+                    lab: slotted.lab,
+                    class: slotted.class,
+                    closed: closure.map(|c| c.reason.clone()),
+                });
+            }
+        }
+        bookings.sort_by_key(|b| (b.date, school.get::<&Slot>(b.slot).clone(), b.lab, b.class));
+        bookings
+    }
+}
+
+pub fn read_calendar(p: impl AsRef<Path>) -> anyhow::Result<Calendar> {
+    let file = File::open(p)?;
+    let calendar: Calendar = serde_json::from_reader(BufReader::new(file))?;
+    if calendar.end < calendar.start {
+        bail!(
+            "The term ends on {} before it starts on {}",
+            calendar.end,
+            calendar.start
+        );
+    }
+    Ok(calendar)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::{school::Day::Monday, solver::Solver, testing};
+
+    #[test]
+    fn books_every_week_but_holidays_and_marks_closures() {
+        let school = testing::school(&[("1A", "Química", "Ana", Monday, "7:30")], &["L1"]);
+        let rules = testing::rules(json!({
+            "classes": [{"subject": "Química", "teachers": [
+                {"name": "Ana", "grades": [{"name": "1A", "labs": ["L1"]}]},
+            ]}],
+        }));
+        let calendar: Calendar = serde_json::from_value(json!({
+            "start": "2026-02-02",
+            "end": "2026-02-20",
+            "holidays": [{"from": "2026-02-16", "reason": "Carnaval"}],
+            "closures": {"L1": [{"from": "2026-02-09", "reason": "Reforma"}]},
+        }))
+        .unwrap();
+        let solution = Solver::new(&school, &rules).solve();
+        let bookings = calendar.bookings(&school, &rules.aliases, &solution, 1);
+        let dates: Vec<_> = (bookings.iter())
+            .map(|b| (b.date.to_string(), b.closed.clone()))
+            .collect();
+        assert_eq!(
+            dates,
+            [
+                ("2026-02-02".into(), None),
+                ("2026-02-09".into(), Some("Reforma".into())),
+            ]
+        );
+        let date = |d: &str| d.parse::<NaiveDate>().unwrap();
+        let weeks =
+            ["2026-02-02", "2026-02-13", "2026-02-16"].map(|d| calendar.week_of(date(d), 2));
+        assert_eq!(weeks, [0, 1, 0]);
+    }
+}
@@ -1,86 +1,349 @@
 use askama::Template;
+use chrono::{Datelike, Days, NaiveDate};
 
 use crate::{
-    school::{ClassRef, Laboratory, School, Teacher},
-    solver::solution::{Error, Solution, Warning},
+    calendar::{Booking, Calendar},
+    rules::Aliases,
+    school::{ClassRef, Grade, Laboratory, School, Slot, Teacher},
+    school_diff::{AffectedRule, Reason, SchoolDiff},
+    solver::solution::{week_name, Cap, Error, LabSlot, LabSlottedClass, Move, Solution, Warning},
 };
 use std::collections::BTreeMap;
 
+/// A cell of a schedule, spanning every period of a session
 #[derive(Debug)]
-struct TeacherSchedule {
-    teacher_name: String,
-    grades: Vec<Option<String>>,
+struct Cell {
+    text: Option<String>,
+    span: usize,
 }
 
+#[derive(Debug)]
+struct Row {
+    name: String,
+    cells: Vec<Cell>,
+}
+
+/// The sessions of a lab, a teacher or a grade in a week
 #[derive(Debug)]
 struct Schedule {
-    lab_name: String,
-    teachers: Vec<TeacherSchedule>,
+    title: String,
+    /// What each row is for
+    row_header: &'static str,
+    rows: Vec<Row>,
+}
+
+/// The schedules of every teacher or every grade
+#[derive(Debug)]
+struct View {
+    title: &'static str,
+    schedules: Vec<Schedule>,
+}
+
+/// How many of a teacher's classes got their first lab choice
+#[derive(Debug)]
+struct Satisfaction {
+    teacher_name: String,
+    satisfied: usize,
+    total: usize,
+    percent: usize,
 }
 
 #[derive(Debug, Template)]
 #[template(path = "index.html")]
 struct Tabulated {
+    /// The schedule of each lab
     schedules: Vec<Schedule>,
+    /// The schedules of each teacher and each grade
+    views: Vec<View>,
+    satisfaction: Vec<Satisfaction>,
     warnings: Vec<String>,
     errors: Vec<String>,
+    changes: Vec<String>,
 }
 
+#[derive(Debug, Template)]
+#[template(path = "alternatives.html")]
+struct Alternatives {
+    alternatives: Vec<Tabulated>,
+}
+
+/// Changes to the classes of a lab, teacher or grade
+#[derive(Debug)]
+struct DiffGroup {
+    name: String,
+    added: Vec<String>,
+    removed: Vec<String>,
+    moved: Vec<String>,
+}
+
+#[derive(Debug)]
+struct DiffSection {
+    title: &'static str,
+    groups: Vec<DiffGroup>,
+}
+
+#[derive(Debug, Template)]
+#[template(path = "diff.html")]
+struct Diff {
+    sections: Vec<DiffSection>,
+    total: usize,
+}
+
+/// A session on a day of a monthly calendar, with every period of it
+#[derive(Debug)]
+struct DatedSession {
+    times: String,
+    class: String,
+    closed: bool,
+}
+
+#[derive(Debug)]
+struct CalendarDay {
+    number: u32,
+    holiday: Option<String>,
+    closed: Option<String>,
+    sessions: Vec<DatedSession>,
+}
+
+/// A row of a monthly calendar, from Monday to Friday, with no day outside the month or the
+/// term
+#[derive(Debug)]
+struct CalendarWeek {
+    name: String,
+    days: Vec<Option<CalendarDay>>,
+}
+
+#[derive(Debug)]
+struct Month {
+    title: String,
+    weeks: Vec<CalendarWeek>,
+}
+
+#[derive(Debug)]
+struct LabCalendar {
+    lab_name: String,
+    months: Vec<Month>,
+}
+
+#[derive(Debug, Template)]
+#[template(path = "calendar.html")]
+struct Calendars {
+    calendars: Vec<LabCalendar>,
+    closed: Vec<String>,
+}
+
+const MONTHS: [&str; 12] = [
+    "Janeiro",
+    "Fevereiro",
+    "Março",
+    "Abril",
+    "Maio",
+    "Junho",
+    "Julho",
+    "Agosto",
+    "Setembro",
+    "Outubro",
+    "Novembro",
+    "Dezembro",
+];
+
 fn group_by<D, K: Ord>(data: Vec<D>, get_key: impl Fn(&D) -> K) -> Vec<(K, Vec<D>)> {
     let mut groups: BTreeMap<K, Vec<_>> = BTreeMap::new();
     for item in data {
         let k = get_key(&item);
-        if groups.contains_key(&k) {
-            groups.get_mut(&k).unwrap().push(item);
-        } else {
-            groups.insert(k, vec![item]);
-        }
+        groups.entry(k).or_default().push(item);
     }
     let mut groups = groups.into_iter().collect::<Vec<_>>();
     groups.sort_by(|a, b| a.0.cmp(&b.0));
     groups
 }
 
-fn tabulate(school: &School, solution: Solution) -> Tabulated {
-    let mut schedules = Vec::new();
-
-    let labs = group_by(solution.slotted, |s| s.lab);
-    for (lab_id, slotted_classes) in labs {
-        let lab: &Laboratory = school.get(lab_id);
-        let lab_name = lab.name.clone();
-        let mut teachers = Vec::new();
-        let grouped = group_by(slotted_classes, |slotted| {
-            school.classes.get(slotted.class).unwrap().teacher
-        });
-
-        for (teacher_id, slotted_classes) in grouped {
-            if slotted_classes.len() == 0 {
-                continue;
+/// The share of each teacher's classes that got their first lab choice, counting the ones left
+/// out as not getting it
+fn satisfaction(school: &School, solution: &Solution) -> Vec<Satisfaction> {
+    let mut classes: BTreeMap<usize, bool> = BTreeMap::new();
+    for slotted in &solution.slotted {
+        classes.insert(slotted.class, true);
+    }
+    for error in &solution.errors {
+        let (Error::Missing(class) | Error::NoLabs(class)) = error;
+        classes.insert(*class, false);
+    }
+    for warning in &solution.warnings {
+        if let Warning::UndesiredLab { class, .. } = warning {
+            classes.insert(*class, false);
+        }
+    }
+    let teachers = group_by(classes.into_iter().collect(), |(class, _)| {
+        school.classes.get(*class).unwrap().teacher
+    });
+    teachers
+        .into_iter()
+        .map(|(teacher_id, classes)| {
+            let satisfied = classes.iter().filter(|(_, first)| *first).count();
+            let total = classes.len();
+            Satisfaction {
+                teacher_name: school.get::<&Teacher>(teacher_id).name.clone(),
+                satisfied,
+                total,
+                percent: satisfied * 100 / total,
             }
+        })
+        .collect()
+}
 
-            // Create a sorted list of slots
-            let mut slots: Vec<_> = school.slots.iter().collect();
-            slots.sort_by(|a, b| a.1.cmp(b.1));
-
-            let mut grades = Vec::new();
-            for (slot_id, _) in slots {
-                let mut found = None;
-                for sl in &slotted_classes {
-                    if sl.slot == *slot_id {
-                        found = Some(school.get::<ClassRef>(sl.class).grade.name.clone());
-                        break;
-                    }
-                }
-                grades.push(found);
-            }
+/// How many weeks the schedule of the solution takes to repeat
+fn weeks(solution: &Solution) -> usize {
+    (solution.slotted.iter())
+        .map(|s| s.week + 1)
+        .max()
+        .unwrap_or(1)
+}
 
-            teachers.push(TeacherSchedule {
-                teacher_name: school.get::<&Teacher>(teacher_id).name.clone(),
-                grades,
-            });
+/// A row of cells for the sessions, each spanning every period of a session
+fn cells(
+    school: &School,
+    slotted: &[LabSlottedClass],
+    text: &impl Fn(&LabSlottedClass) -> String,
+) -> Vec<Cell> {
+    // Create a sorted list of slots
+    let mut slots: Vec<_> = school.slots.iter().collect();
+    slots.sort_by(|a, b| a.1.cmp(b.1));
+
+    let mut cells: Vec<Cell> = Vec::new();
+    let mut last: Option<(usize, &Slot)> = None;
+    for (slot_id, slot) in slots {
+        let found = slotted.iter().find(|sl| sl.slot == *slot_id);
+        match (found, cells.last_mut(), last) {
+            // The next period of the session in the previous cell
+            (Some(sl), Some(cell), Some((class, previous)))
+                if sl.class == class && previous.day == slot.day =>
+            {
+                cell.span += 1;
+            }
+            _ => cells.push(Cell {
+                text: found.map(text),
+                span: 1,
+            }),
         }
-        schedules.push(Schedule { lab_name, teachers });
+        last = found.map(|sl| (sl.class, slot));
     }
+    cells
+}
+
+/// A schedule for each table of each week of the cycle, with a row for each row in it. Tables
+/// and rows are ordered by the id that comes with their names.
+fn schedules_of(
+    school: &School,
+    slotted: &[LabSlottedClass],
+    cycle: usize,
+    row_header: &'static str,
+    table: impl Fn(&LabSlottedClass) -> (usize, String),
+    row: impl Fn(&LabSlottedClass) -> (usize, String),
+    text: impl Fn(&LabSlottedClass) -> String,
+) -> Vec<Schedule> {
+    let tables = group_by(slotted.to_vec(), |s| (table(s), s.week));
+    tables
+        .into_iter()
+        .map(|(((_, name), week), slotted)| {
+            let title = if cycle > 1 {
+                format!("{name} — Semana {}", week_name(week))
+            } else {
+                name
+            };
+            let rows = group_by(slotted, &row)
+                .into_iter()
+                .map(|((_, name), slotted)| Row {
+                    name,
+                    cells: cells(school, &slotted, &text),
+                })
+                .collect();
+            Schedule {
+                title,
+                row_header,
+                rows,
+            }
+        })
+        .collect()
+}
+
+fn tabulate(school: &School, solution: Solution) -> Tabulated {
+    let satisfaction = satisfaction(school, &solution);
+    let cycle = weeks(&solution);
+    // The labs each class is in at once, more than one when split into half-groups
+    let mut labs_at: BTreeMap<(usize, usize, usize), Vec<usize>> = BTreeMap::new();
+    for s in &solution.slotted {
+        labs_at
+            .entry((s.class, s.slot, s.week))
+            .or_default()
+            .push(s.lab);
+    }
+    let split = |sl: &LabSlottedClass| {
+        let others: Vec<_> = labs_at[&(sl.class, sl.slot, sl.week)]
+            .iter()
+            .filter(|&&other| other != sl.lab)
+            .map(|&other| school.get::<&Laboratory>(other).name.as_str())
+            .collect();
+        if others.is_empty() {
+            String::new()
+        } else {
+            format!(" (½ com {})", others.join(", "))
+        }
+    };
+    let lab = |sl: &LabSlottedClass| (sl.lab, school.get::<&Laboratory>(sl.lab).name.clone());
+    let teacher = |sl: &LabSlottedClass| {
+        let teacher_id = school.classes.get(sl.class).unwrap().teacher;
+        (teacher_id, school.get::<&Teacher>(teacher_id).name.clone())
+    };
+    let grade = |sl: &LabSlottedClass| {
+        let grade_id = school.classes.get(sl.class).unwrap().grade;
+        (grade_id, school.get::<&Grade>(grade_id).name.clone())
+    };
+    let grade_name = |sl: &LabSlottedClass| {
+        let class: ClassRef = school.get(sl.class);
+        format!("{}{}", class.grade, split(sl))
+    };
+    let subject_name = |sl: &LabSlottedClass| {
+        let class: ClassRef = school.get(sl.class);
+        format!("{} ({}){}", class.subject, class.teacher, split(sl))
+    };
+
+    let slotted = &solution.slotted;
+    let schedules = schedules_of(
+        school,
+        slotted,
+        cycle,
+        "Professor",
+        lab,
+        teacher,
+        grade_name,
+    );
+    let views = vec![
+        View {
+            title: "Por professor",
+            schedules: schedules_of(
+                school,
+                slotted,
+                cycle,
+                "Laboratório",
+                teacher,
+                lab,
+                grade_name,
+            ),
+        },
+        View {
+            title: "Por turma",
+            schedules: schedules_of(
+                school,
+                slotted,
+                cycle,
+                "Laboratório",
+                grade,
+                lab,
+                subject_name,
+            ),
+        },
+    ];
 
     let mut warnings = vec![];
     let mut errors = vec![];
@@ -95,9 +358,53 @@ fn tabulate(school: &School, solution: Solution) -> Tabulated {
                     "A aula {class} não recebeu sua primeira opção de laboratório (era {was}, recebeu {got})."
                 ));
             }
+            Warning::UndesiredTime { class, slot } => {
+                let class: ClassRef = school.get(class);
+                let slot: &Slot = school.get(slot);
+                warnings.push(format!(
+                    "A aula {class} ficou em um horário que o professor preferia evitar ({slot})."
+                ));
+            }
+            Warning::CapLimited { class, cap, limit } => {
+                let class: ClassRef = school.get(class);
+                let in_week = |week| {
+                    if cycle > 1 {
+                        format!(", semana {}", week_name(week))
+                    } else {
+                        String::new()
+                    }
+                };
+                let cap = match cap {
+                    Cap::TeacherPerWeek(teacher, week) => {
+                        let teacher: &Teacher = school.get(teacher);
+                        let week = in_week(week);
+                        format!("{limit} aulas por semana de {teacher}{week}")
+                    }
+                    Cap::GradePerDay(grade, week, day) => {
+                        let grade: &Grade = school.get(grade);
+                        let week = in_week(week);
+                        format!("{limit} aulas por dia da turma {grade} ({day:?}{week})")
+                    }
+                    Cap::LabPerDay(lab, week, day) => {
+                        let lab: &Laboratory = school.get(lab);
+                        let week = in_week(week);
+                        format!("{limit} aulas por dia em {lab} ({day:?}{week})")
+                    }
+                };
+                warnings.push(format!(
+                    "A aula {class} poderia ter sido melhor alocada se não fosse o limite de {cap}."
+                ));
+            }
         }
     }
 
+    if solution.cancelled {
+        errors.push(
+            "A resolução foi cancelada antes de terminar; algumas aulas podem não ter sido alocadas."
+                .to_string(),
+        );
+    }
+
     for error in solution.errors {
         match error {
             Error::Missing(i) => {
@@ -111,10 +418,216 @@ fn tabulate(school: &School, solution: Solution) -> Tabulated {
         }
     }
 
+    let changes = solution
+        .moved
+        .iter()
+        .map(|moved| describe_move(school, moved))
+        .collect();
+
     Tabulated {
         schedules,
+        views,
+        satisfaction,
         warnings,
         errors,
+        changes,
+    }
+}
+
+/// Lay the bookings of each lab out in a calendar of every month of the term
+fn calendars(
+    school: &School,
+    aliases: &Aliases,
+    calendar: &Calendar,
+    bookings: &[Booking],
+    weeks: usize,
+) -> Calendars {
+    let date = |date: NaiveDate| date.format("%d/%m/%Y").to_string();
+    let closed = (bookings.iter())
+        .filter_map(|booking| {
+            let reason = booking.closed.as_ref()?;
+            let class: ClassRef = school.get(booking.class);
+            let lab: &Laboratory = school.get(booking.lab);
+            let slot: &Slot = school.get(booking.slot);
+            let reason = if reason.is_empty() {
+                String::new()
+            } else {
+                format!(": {reason}")
+            };
+            Some(format!(
+                "A aula {class} cai em {} ({slot}), quando {lab} está fechado{reason}.",
+                date(booking.date)
+            ))
+        })
+        .collect();
+
+    let mut months = vec![];
+    let mut first = calendar.start.with_day(1).unwrap();
+    while first <= calendar.end {
+        months.push(first);
+        first = first.checked_add_months(chrono::Months::new(1)).unwrap();
+    }
+
+    let by_lab = group_by(bookings.to_vec(), |b| b.lab);
+    let calendars = by_lab
+        .into_iter()
+        .map(|(lab_id, bookings)| {
+            let by_date = group_by(bookings, |b| b.date);
+            let day = |date: NaiveDate| {
+                let holiday = calendar.holiday(date).map(|h| h.reason.clone());
+                let closed =
+                    (calendar.closure(school, aliases, lab_id, date)).map(|c| c.reason.clone());
+                let bookings = (by_date.iter())
+                    .find(|(d, _)| *d == date)
+                    .map(|(_, bookings)| bookings.clone())
+                    .unwrap_or_default();
+                let sessions = group_by(bookings, |b| b.class)
+                    .into_iter()
+                    .map(|(class_id, bookings)| {
+                        let times: Vec<_> = (bookings.iter())
+                            .map(|b| school.get::<&Slot>(b.slot).time.to_string())
+                            .collect();
+                        DatedSession {
+                            times: times.join(", "),
+                            class: school.get::<ClassRef>(class_id).to_string(),
+                            closed: bookings.iter().any(|b| b.closed.is_some()),
+                        }
+                    })
+                    .collect::<Vec<_>>();
+                let mut sessions = sessions;
+                sessions.sort_by(|a, b| a.times.cmp(&b.times));
+                CalendarDay {
+                    number: date.day(),
+                    holiday,
+                    closed,
+                    sessions,
+                }
+            };
+            let months = (months.iter())
+                .map(|&first| {
+                    let mut monday = first.week(chrono::Weekday::Mon).first_day();
+                    // A month starting on a weekend starts on the next week
+                    if monday + Days::new(4) < first {
+                        monday = monday + Days::new(7);
+                    }
+                    let mut weeks_of_month = vec![];
+                    while monday.month() == first.month() || monday < first {
+                        let mut name = monday.format("%d/%m").to_string();
+                        if weeks > 1 {
+                            let week = calendar.week_of(monday, weeks);
+                            name = format!("{name} (Semana {})", week_name(week));
+                        }
+                        let days = (0..5)
+                            .map(|i| {
+                                let date = monday + Days::new(i);
+                                let in_term = calendar.start <= date && date <= calendar.end;
+                                (date.month() == first.month() && in_term).then(|| day(date))
+                            })
+                            .collect();
+                        weeks_of_month.push(CalendarWeek { name, days });
+                        monday = monday + Days::new(7);
+                    }
+                    Month {
+                        title: format!("{} de {}", MONTHS[first.month0() as usize], first.year()),
+                        weeks: weeks_of_month,
+                    }
+                })
+                .collect();
+            LabCalendar {
+                lab_name: school.get::<&Laboratory>(lab_id).name.clone(),
+                months,
+            }
+        })
+        .collect();
+    Calendars { calendars, closed }
+}
+
+fn describe_lab_slots(school: &School, slots: &[LabSlot]) -> String {
+    let cycle = slots.iter().any(|s| s.week > 0);
+    slots
+        .iter()
+        .map(|&LabSlot { lab, slot, week }| {
+            let lab: &Laboratory = school.get(lab);
+            let slot: &Slot = school.get(slot);
+            if cycle {
+                format!("{lab} ({slot}, semana {})", week_name(week))
+            } else {
+                format!("{lab} ({slot})")
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn describe_move(school: &School, moved: &Move) -> String {
+    let class: ClassRef = school.get(moved.class);
+    let from = describe_lab_slots(school, &moved.from);
+    let to = describe_lab_slots(school, &moved.to);
+    match (from.is_empty(), to.is_empty()) {
+        (true, _) => format!("A aula {class} passou a ser alocada em {to}."),
+        (_, true) => format!("A aula {class} deixou de ser alocada (estava em {from})."),
+        _ => format!("A aula {class} mudou de {from} para {to}."),
+    }
+}
+
+/// Group the changes between two solutions by lab, teacher and grade. A class that moved
+/// between labs shows up in both.
+fn diff(school: &School, before: &Solution, after: &Solution) -> Diff {
+    let moves = after.moves_from(before);
+    let section = |title, keyed: Vec<(String, &Move)>| {
+        let groups = group_by(keyed, |(name, _)| name.clone())
+            .into_iter()
+            .map(|(name, moves)| {
+                let mut group = DiffGroup {
+                    name,
+                    added: vec![],
+                    removed: vec![],
+                    moved: vec![],
+                };
+                for (_, moved) in moves {
+                    let list = match (moved.from.is_empty(), moved.to.is_empty()) {
+                        (true, _) => &mut group.added,
+                        (_, true) => &mut group.removed,
+                        _ => &mut group.moved,
+                    };
+                    list.push(describe_move(school, moved));
+                }
+                group
+            })
+            .collect();
+        DiffSection { title, groups }
+    };
+    let by_lab = moves
+        .iter()
+        .flat_map(|moved| {
+            let mut labs: Vec<_> = moved.from.iter().chain(&moved.to).map(|s| s.lab).collect();
+            labs.sort();
+            labs.dedup();
+            labs.into_iter()
+                .map(move |lab| (school.get::<&Laboratory>(lab).name.clone(), moved))
+        })
+        .collect();
+    let by_teacher = moves
+        .iter()
+        .map(|moved| {
+            let class: ClassRef = school.get(moved.class);
+            (class.teacher.name.clone(), moved)
+        })
+        .collect();
+    let by_grade = moves
+        .iter()
+        .map(|moved| {
+            let class: ClassRef = school.get(moved.class);
+            (class.grade.name.clone(), moved)
+        })
+        .collect();
+    Diff {
+        sections: vec![
+            section("Por laboratório", by_lab),
+            section("Por professor", by_teacher),
+            section("Por turma", by_grade),
+        ],
+        total: moves.len(),
     }
 }
 
@@ -146,6 +659,192 @@ fn tabulate(school: &School, solution: Solution) -> Tabulated {
 // }
 
 pub fn html(school: &School, solution: Solution) {
-    let tabulated = tabulate(&school, solution);
+    let tabulated = tabulate(school, solution);
     println!("{}", tabulated.render().unwrap());
 }
+
+/// Print several solutions to the same problem side by side
+pub fn html_alternatives(school: &School, solutions: Vec<Solution>) {
+    let alternatives = Alternatives {
+        alternatives: solutions
+            .into_iter()
+            .map(|solution| tabulate(school, solution))
+            .collect(),
+    };
+    println!("{}", alternatives.render().unwrap());
+}
+
+/// Print the sessions of every lab on the dates of the term, a month at a time
+pub fn calendar_html(
+    school: &School,
+    aliases: &Aliases,
+    calendar: &Calendar,
+    bookings: &[Booking],
+    weeks: usize,
+) {
+    let calendars = calendars(school, aliases, calendar, bookings, weeks);
+    println!("{}", calendars.render().unwrap());
+}
+
+/// Print what changed from one solution to another as plain text
+pub fn diff_text(school: &School, before: &Solution, after: &Solution) {
+    let diff = diff(school, before, after);
+    if diff.total == 0 {
+        println!("Nenhuma aula mudou.");
+        return;
+    }
+    println!("{} aulas mudaram.", diff.total);
+    for section in diff.sections {
+        println!();
+        println!("{}", section.title);
+        for group in section.groups {
+            println!("  {}", group.name);
+            for (mark, lines) in [("+", group.added), ("-", group.removed), ("~", group.moved)] {
+                for line in lines {
+                    println!("    {mark} {line}");
+                }
+            }
+        }
+    }
+}
+
+/// Print what changed from one solution to another as HTML
+pub fn diff_html(school: &School, before: &Solution, after: &Solution) {
+    let diff = diff(school, before, after);
+    println!("{}", diff.render().unwrap());
+}
+
+/// Print what changed between two imports of the school, and which rules need another look
+pub fn school_diff_text(diff: &SchoolDiff, affected: &[AffectedRule]) {
+    if diff.is_empty() {
+        println!("Nada mudou no horário da escola.");
+        return;
+    }
+    for (title, changes) in [
+        ("Professores", &diff.teachers),
+        ("Turmas", &diff.grades),
+        ("Disciplinas", &diff.subjects),
+        ("Laboratórios", &diff.labs),
+    ] {
+        for name in &changes.added {
+            println!("+ {title}: {name}");
+        }
+        for name in &changes.removed {
+            println!("- {title}: {name}");
+        }
+    }
+    for class in &diff.classes.added {
+        println!(
+            "+ Aula: {} - {}/{}",
+            class.teacher, class.grade, class.subject
+        );
+    }
+    for class in &diff.classes.removed {
+        println!(
+            "- Aula: {} - {}/{}",
+            class.teacher, class.grade, class.subject
+        );
+    }
+    let slots = |slots: &[Slot]| {
+        slots
+            .iter()
+            .map(|s| s.to_string())
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    for moved in &diff.moved {
+        let class = &moved.class;
+        println!(
+            "~ Aula: {} - {}/{} mudou de {} para {}",
+            class.teacher,
+            class.grade,
+            class.subject,
+            slots(&moved.from),
+            slots(&moved.to)
+        );
+    }
+    if affected.is_empty() {
+        return;
+    }
+    println!();
+    println!("Regras afetadas");
+    for rule in affected {
+        let class = &rule.class;
+        let reason = match &rule.reason {
+            Reason::TeacherRemoved => "o professor não existe mais".to_string(),
+            Reason::GradeRemoved => "a turma não existe mais".to_string(),
+            Reason::SubjectRemoved => "a disciplina não existe mais".to_string(),
+            Reason::ClassRemoved => "a aula não existe mais".to_string(),
+            Reason::ClassMoved => "a aula mudou de horário".to_string(),
+            Reason::LabRemoved(lab) => format!("o laboratório {lab} não existe mais"),
+        };
+        println!(
+            "  {} - {}/{}: {reason}",
+            class.teacher, class.grade, class.subject
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::{school::Day::Monday, solver::Solver, testing};
+
+    /// The name of a row with the text of each of its cells
+    type Texts<'a> = (&'a str, Vec<Option<&'a str>>);
+
+    /// The title of each schedule of a view, with the texts of each row
+    fn rows(view: &View) -> Vec<(&str, Vec<Texts<'_>>)> {
+        (view.schedules.iter())
+            .map(|schedule| {
+                let rows = (schedule.rows.iter())
+                    .map(|row| {
+                        let cells = row.cells.iter().map(|c| c.text.as_deref()).collect();
+                        (row.name.as_str(), cells)
+                    })
+                    .collect();
+                (schedule.title.as_str(), rows)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn lays_out_views_by_teacher_and_grade() {
+        let school = testing::school(
+            &[
+                ("1A", "Química", "Ana", Monday, "7:30"),
+                ("1B", "Física", "Bruno", Monday, "8:20"),
+            ],
+            &["L1", "L2"],
+        );
+        let rules = testing::rules(json!({
+            "classes": [
+                {"subject": "Química", "teachers": [
+                    {"name": "Ana", "grades": [{"name": "1A", "labs": ["L1"]}]},
+                ]},
+                {"subject": "Física", "teachers": [
+                    {"name": "Bruno", "grades": [{"name": "1B", "labs": ["L2"]}]},
+                ]},
+            ],
+        }));
+        let tabulated = tabulate(&school, Solver::new(&school, &rules).solve());
+        let titles: Vec<_> = tabulated.views.iter().map(|v| v.title).collect();
+        assert_eq!(titles, ["Por professor", "Por turma"]);
+        assert_eq!(
+            rows(&tabulated.views[0]),
+            [
+                ("Ana", vec![("L1", vec![Some("1A"), None])]),
+                ("Bruno", vec![("L2", vec![None, Some("1B")])]),
+            ]
+        );
+        assert_eq!(
+            rows(&tabulated.views[1]),
+            [
+                ("1A", vec![("L1", vec![Some("Química (Ana)"), None])]),
+                ("1B", vec![("L2", vec![None, Some("Física (Bruno)")])]),
+            ]
+        );
+    }
+}
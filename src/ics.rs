@@ -0,0 +1,238 @@
+use std::{
+    collections::BTreeMap,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use chrono::{DateTime, Datelike, NaiveDate, NaiveDateTime, NaiveTime, TimeDelta};
+
+use crate::{
+    calendar::{weekday, Calendar},
+    school::{ClassRef, Laboratory, School, Slot, Time},
+    solver::solution::Solution,
+};
+
+/// Longest line of an iCalendar file in octets, longer ones are folded
+const MAX_LINE: usize = 75;
+
+/// An iCalendar file for a lab, a teacher or a grade
+#[derive(Debug)]
+pub struct Feed {
+    /// File name, without the extension
+    pub name: String,
+    pub contents: String,
+}
+
+/// One session of a class, repeating every cycle of the schedule
+#[derive(Debug)]
+struct Event {
+    class: usize,
+    lab: usize,
+    week: usize,
+    /// Consecutive periods, first to last
+    slots: Vec<usize>,
+}
+
+/// Escape the characters with a meaning in iCalendar text values
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+}
+
+/// Break a line into ones of at most [`MAX_LINE`] octets, the later ones starting with a space
+fn fold(line: &str, out: &mut String) {
+    let mut width = 0;
+    for c in line.chars() {
+        if width + c.len_utf8() > MAX_LINE {
+            out.push_str("\r\n ");
+            width = 1;
+        }
+        out.push(c);
+        width += c.len_utf8();
+    }
+    out.push_str("\r\n");
+}
+
+fn local(date: NaiveDate, Time(hour, minute): Time) -> NaiveDateTime {
+    let time = NaiveTime::from_hms_opt(hour.into(), minute.into(), 0).unwrap();
+    date.and_time(time)
+}
+
+fn format(date_time: NaiveDateTime) -> String {
+    date_time.format("%Y%m%dT%H%M%S").to_string()
+}
+
+/// Every session of the solution, with the periods of a double session together
+fn events(school: &School, solution: &Solution) -> Vec<Event> {
+    let mut slotted: Vec<_> = solution.slotted.iter().collect();
+    slotted.sort_by_key(|s| (s.class, s.lab, s.week, school.get::<&Slot>(s.slot)));
+    let mut events: Vec<Event> = vec![];
+    for s in slotted {
+        match events.last_mut() {
+            Some(event)
+                if (event.class, event.lab, event.week) == (s.class, s.lab, s.week)
+                    && school.next_slot(*event.slots.last().unwrap()) == Some(s.slot) =>
+            {
+                event.slots.push(s.slot);
+            }
+            _ => events.push(Event {
+                class: s.class,
+                lab: s.lab,
+                week: s.week,
+                slots: vec![s.slot],
+            }),
+        }
+    }
+    events
+}
+
+/// Write the events as a calendar, each repeating weekly over the term, every `weeks` weeks,
+/// except on holidays
+fn write_calendar(
+    school: &School,
+    calendar: &Calendar,
+    weeks: usize,
+    title: &str,
+    events: &[&Event],
+) -> String {
+    let stamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .ok()
+        .and_then(|elapsed| DateTime::from_timestamp(elapsed.as_secs() as i64, 0))
+        .unwrap_or_default()
+        .format("%Y%m%dT%H%M%SZ");
+    let mut out = String::new();
+    let mut line = |line: String| fold(&line, &mut out);
+    line("BEGIN:VCALENDAR".into());
+    line("VERSION:2.0".into());
+    line("PRODID:-//labs//Laboratórios//PT".into());
+    line("CALSCALE:GREGORIAN".into());
+    line(format!("X-WR-CALNAME:{}", escape(title)));
+    for event in events {
+        let first: &Slot = school.get(event.slots[0]);
+        let last: &Slot = school.get(*event.slots.last().unwrap());
+        let mut dates = (calendar.dates())
+            .filter(|d| d.weekday() == weekday(first.day))
+            .filter(|d| calendar.week_of(*d, weeks) == event.week);
+        let Some(start) = dates.next() else {
+            continue;
+        };
+        let end = local(start, last.time) + TimeDelta::minutes(calendar.period.into());
+        let holidays: Vec<_> = std::iter::once(start)
+            .chain(dates)
+            .filter(|d| calendar.holiday(*d).is_some())
+            .map(|d| format(local(d, first.time)))
+            .collect();
+        let class: ClassRef = school.get(event.class);
+        let lab: &Laboratory = school.get(event.lab);
+        line("BEGIN:VEVENT".into());
+        line(format!(
+            "UID:{}-{}-{}-{}@labs",
+            event.class, event.lab, event.slots[0], event.week
+        ));
+        line(format!("DTSTAMP:{stamp}"));
+        line(format!("DTSTART:{}", format(local(start, first.time))));
+        line(format!("DTEND:{}", format(end)));
+        line(format!(
+            "RRULE:FREQ=WEEKLY;INTERVAL={};UNTIL={}",
+            weeks.max(1),
+            format(local(calendar.end, Time(23, 59)))
+        ));
+        if !holidays.is_empty() {
+            line(format!("EXDATE:{}", holidays.join(",")));
+        }
+        line(format!(
+            "SUMMARY:{}",
+            escape(&format!("{} - {}", class.subject, class.grade))
+        ));
+        line(format!("LOCATION:{}", escape(&lab.name)));
+        line(format!(
+            "DESCRIPTION:{}",
+            escape(&format!("Professor: {}", class.teacher))
+        ));
+        line("END:VEVENT".into());
+    }
+    line("END:VCALENDAR".into());
+    out
+}
+
+/// A calendar of the sessions of each lab, each teacher and each grade
+pub fn feeds(school: &School, calendar: &Calendar, solution: &Solution, weeks: usize) -> Vec<Feed> {
+    let events = events(school, solution);
+    let mut groups: BTreeMap<(usize, String), (String, Vec<&Event>)> = BTreeMap::new();
+    for event in &events {
+        let class: ClassRef = school.get(event.class);
+        let lab: &Laboratory = school.get(event.lab);
+        let keys = [
+            (0, lab.name.clone(), format!("Laboratório {}", lab.name)),
+            (
+                1,
+                class.teacher.name.clone(),
+                format!("Aulas de laboratório de {}", class.teacher),
+            ),
+            (
+                2,
+                class.grade.name.clone(),
+                format!("Aulas de laboratório da turma {}", class.grade),
+            ),
+        ];
+        for (kind, name, title) in keys {
+            let group = groups.entry((kind, name)).or_insert((title, vec![]));
+            group.1.push(event);
+        }
+    }
+    groups
+        .into_iter()
+        .map(|((kind, name), (title, events))| {
+            let prefix = ["Laboratório", "Professor", "Turma"][kind];
+            // Characters that can't be in file names on some systems
+            let name: String = (name.chars())
+                .map(|c| if r#"/\:*?"<>|"#.contains(c) { '-' } else { c })
+                .collect();
+            Feed {
+                name: format!("{prefix} {name}"),
+                contents: write_calendar(school, calendar, weeks, &title, &events),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::{school::Day::Monday, solver::Solver, testing};
+
+    #[test]
+    fn repeats_weekly_except_on_holidays() {
+        let school = testing::school(&[("1A", "Química", "Ana", Monday, "7:30")], &["L1"]);
+        let rules = testing::rules(json!({
+            "classes": [{"subject": "Química", "teachers": [
+                {"name": "Ana", "grades": [{"name": "1A", "labs": ["L1"]}]},
+            ]}],
+        }));
+        let calendar: Calendar = serde_json::from_value(json!({
+            "start": "2026-02-02",
+            "end": "2026-02-27",
+            "holidays": [{"from": "2026-02-16", "reason": "Carnaval"}],
+        }))
+        .unwrap();
+        let solution = Solver::new(&school, &rules).solve();
+        let feeds = feeds(&school, &calendar, &solution, 1);
+        let names: Vec<_> = feeds.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(names, ["Laboratório L1", "Professor Ana", "Turma 1A"]);
+        let lines: Vec<_> = feeds[0].contents.split("\r\n").collect();
+        for expected in [
+            "DTSTART:20260202T073000",
+            "DTEND:20260202T082000",
+            "RRULE:FREQ=WEEKLY;INTERVAL=1;UNTIL=20260227T235900",
+            "EXDATE:20260216T073000",
+            "SUMMARY:Química - 1A",
+            "LOCATION:L1",
+        ] {
+            assert!(lines.contains(&expected), "missing {expected}");
+        }
+    }
+}
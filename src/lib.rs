@@ -1,6 +1,12 @@
+pub mod calendar;
 pub mod export;
+pub mod ics;
 pub mod rules;
 pub mod school;
+pub mod school_diff;
 pub mod solver;
 pub mod sources;
 pub mod table;
+#[cfg(test)]
+mod testing;
+pub mod validate;
@@ -1,12 +1,334 @@
+use std::{
+    fs::{self, File},
+    io,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use clap::{Parser, Subcommand};
 use labs::*;
 
-use rules::load_rules;
+use calendar::read_calendar;
+use rules::{read_rules, Rules};
+use school::{ClassRef, Laboratory, School, Slot};
+use school_diff::SchoolDiff;
+use solver::{
+    external::Format,
+    saved::{read_solution, save_solution},
+    solution::{Solution, Statistics},
+    verify::verify,
+    Backend, Improvement, Objective, Solver,
+};
 use sources::html::load_school;
+use validate::{uneven_weeks, validate};
+
+/// Seconds of improvement when only `--objective fair` asks for it
+const FAIR_IMPROVEMENT: f64 = 5.0;
+
+/// A number of seconds that can be waited for
+fn seconds(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(seconds) if seconds.is_finite() && seconds >= 0.0 => Ok(seconds),
+        Ok(_) => Err("must be a number of seconds, 0 or more".into()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+#[derive(Parser)]
+struct Cli {
+    #[arg(long, default_value = "input/school.html")]
+    school: PathBuf,
+    #[arg(long, default_value = "input/labs.json")]
+    labs: PathBuf,
+    #[arg(long, default_value = "input/rules.json")]
+    rules: PathBuf,
+    /// Dates of the term, holidays and lab closures
+    #[arg(long, default_value = "input/calendar.json")]
+    calendar: PathBuf,
+    /// How to search for the schedule: backtracking or sat
+    #[arg(long, default_value = "backtracking")]
+    backend: Backend,
+    /// Spend this many seconds improving the schedule after solving
+    #[arg(long, value_parser = seconds)]
+    improve: Option<f64>,
+    /// Seed of the improvement phase
+    #[arg(long, default_value_t = 0)]
+    seed: u64,
+    /// What the schedule aims for: total, or fair to spread lost lab choices evenly across
+    /// teachers and grades. The search itself only aims for the total, so fair always runs the
+    /// improvement phase, for a few seconds when --improve is not given.
+    #[arg(long, default_value = "total")]
+    objective: Objective,
+    /// A saved schedule to change as little as possible
+    #[arg(long)]
+    previous: Option<PathBuf>,
+    /// Save the schedule as JSON to this file
+    #[arg(long)]
+    save: Option<PathBuf>,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Solve the schedule and print it as HTML (the default)
+    Solve,
+    /// Solve several different schedules and print them side by side as HTML
+    Alternatives {
+        /// How many schedules to look for
+        #[arg(short = 'n', long, default_value_t = 3)]
+        count: usize,
+        /// How many classes each schedule must place differently than the others
+        #[arg(short = 'k', long, default_value_t = 1)]
+        min_difference: usize,
+    },
+    /// Print the problem for an external solver: minizinc, lp or dimacs
+    ExportProblem {
+        #[arg(short, long, default_value = "minizinc")]
+        format: Format,
+    },
+    /// Read the output of an external solver for the exported problem and print it as HTML
+    ImportSolution {
+        #[arg(short, long, default_value = "minizinc")]
+        format: Format,
+        /// The output of the external solver
+        output: PathBuf,
+    },
+    /// Show what changed from a saved schedule to another one, or to a fresh solve
+    Diff {
+        /// The saved schedule from before
+        before: PathBuf,
+        /// The saved schedule from after, solving again if missing
+        after: Option<PathBuf>,
+        /// Print HTML instead of text
+        #[arg(long)]
+        html: bool,
+    },
+    /// Show what changed from an older import of the school, and which rules are affected
+    SchoolDiff {
+        /// The older school timetable
+        old_school: PathBuf,
+        /// The older list of labs, the current one if missing
+        #[arg(long)]
+        old_labs: Option<PathBuf>,
+    },
+    /// Print the schedule on the dates of the term as monthly lab calendars in HTML
+    Calendar {
+        /// The saved schedule, solving again if missing
+        solution: Option<PathBuf>,
+    },
+    /// Write an iCalendar file of the sessions of each lab, teacher and grade over the term
+    Ics {
+        /// Where to write the files
+        dir: PathBuf,
+        /// The saved schedule, solving again if missing
+        solution: Option<PathBuf>,
+    },
+    /// Check that every name in the rules is in the school
+    Validate,
+    /// Check a saved schedule against the rules
+    Verify {
+        /// The saved schedule
+        solution: PathBuf,
+    },
+}
+
+fn show_progress(statistics: &Statistics) {
+    eprint!("\r\x1b[2KSolving: {statistics}");
+}
+
+/// Warn about every rule the solution breaks
+fn check(school: &School, rules: &Rules, solution: &Solution) {
+    for violation in verify(school, rules, solution) {
+        eprintln!("Violation: {}", violation.describe(school));
+    }
+}
+
+/// Load a saved schedule, warning about every entry left out
+fn load_saved(school: &School, p: impl AsRef<Path>) -> anyhow::Result<Solution> {
+    let loaded = read_solution(school, p)?;
+    for skipped in &loaded.skipped {
+        eprintln!("Skipped in the saved schedule: {skipped}");
+    }
+    Ok(loaded.solution)
+}
+
+/// Load a saved schedule, or solve for one if there is none
+fn saved_or_solved(
+    school: &School,
+    rules: &Rules,
+    backend: Backend,
+    saved: Option<PathBuf>,
+) -> anyhow::Result<Solution> {
+    if let Some(saved) = saved {
+        return load_saved(school, saved);
+    }
+    let solution = Solver::new(school, rules)
+        .backend(backend)
+        .on_progress(show_progress)
+        .solve();
+    eprintln!("\r\x1b[2KSolved: {}", solution.statistics);
+    Ok(solution)
+}
 
 fn main() -> anyhow::Result<()> {
-    let school = load_school("input/school.html", "input/labs.json")?;
-    let rules = load_rules("input/rules.json")?;
-    let solution = solver::solve(&school, &rules);
-    export::html(&school, solution);
+    let mut cli = Cli::parse();
+    let loaded = read_rules(&cli.rules)?;
+    for field in &loaded.unknown_fields {
+        eprintln!("Unknown field in the rules: {field}");
+    }
+    if loaded.is_outdated() {
+        eprintln!(
+            "The rules are in an old format (version {}), save them in the rule editor to upgrade",
+            loaded.version
+        );
+    }
+    let rules = loaded.rules;
+    let school = load_school(&cli.school, &cli.labs, &rules.aliases)?;
+    let diagnostics = validate(&school, &rules);
+    let uneven = uneven_weeks(&rules);
+    let command = cli.command.take().unwrap_or(Command::Solve);
+    if !matches!(command, Command::Validate) {
+        for diagnostic in &diagnostics {
+            eprintln!("{diagnostic}");
+        }
+        for uneven in &uneven {
+            eprintln!("{uneven}");
+        }
+    }
+    let previous = match &cli.previous {
+        Some(p) => Some(load_saved(&school, p)?),
+        None => None,
+    };
+    match command {
+        Command::Solve => {
+            let mut solver = Solver::new(&school, &rules)
+                .backend(cli.backend)
+                .on_progress(show_progress);
+            if let Some(previous) = &previous {
+                solver = solver.keep_close_to(previous);
+            }
+            let improve = match cli.objective {
+                Objective::Fair => cli.improve.or(Some(FAIR_IMPROVEMENT)),
+                Objective::Total => cli.improve,
+            };
+            if let Some(seconds) = improve {
+                solver = solver.improve(Improvement {
+                    time: Duration::from_secs_f64(seconds),
+                    seed: cli.seed,
+                    objective: cli.objective,
+                });
+            }
+            let solution = solver.solve();
+            eprintln!("\r\x1b[2KSolved: {}", solution.statistics);
+            check(&school, &rules, &solution);
+            if let Some(p) = &cli.save {
+                save_solution(&school, &solution, p)?;
+            }
+            export::html(&school, solution);
+        }
+        Command::Alternatives {
+            count,
+            min_difference,
+        } => {
+            let mut solver = Solver::new(&school, &rules)
+                .backend(cli.backend)
+                .on_progress(show_progress);
+            if let Some(previous) = &previous {
+                solver = solver.keep_close_to(previous);
+            }
+            let solutions = solver.solve_many(count, min_difference);
+            eprintln!("\r\x1b[2KFound {} of {count} schedules", solutions.len());
+            for solution in &solutions {
+                check(&school, &rules, solution);
+            }
+            export::html_alternatives(&school, solutions);
+        }
+        Command::ExportProblem { format } => {
+            Solver::new(&school, &rules).export_problem(format, io::stdout().lock())?;
+        }
+        Command::ImportSolution { format, output } => {
+            let solution =
+                Solver::new(&school, &rules).import_solution(format, File::open(output)?)?;
+            check(&school, &rules, &solution);
+            if let Some(p) = &cli.save {
+                save_solution(&school, &solution, p)?;
+            }
+            export::html(&school, solution);
+        }
+        Command::Diff {
+            before,
+            after,
+            html,
+        } => {
+            let before = load_saved(&school, before)?;
+            let after = saved_or_solved(&school, &rules, cli.backend, after)?;
+            if html {
+                export::diff_html(&school, &before, &after);
+            } else {
+                export::diff_text(&school, &before, &after);
+            }
+        }
+        Command::SchoolDiff {
+            old_school,
+            old_labs,
+        } => {
+            let old = load_school(
+                old_school,
+                old_labs.as_ref().unwrap_or(&cli.labs),
+                &rules.aliases,
+            )?;
+            let diff = SchoolDiff::new(&old, &school);
+            export::school_diff_text(&diff, &diff.affected_rules(&rules, &old));
+        }
+        Command::Calendar { solution } => {
+            let calendar = read_calendar(&cli.calendar)?;
+            for lab in calendar.unknown_labs(&school, &rules.aliases) {
+                eprintln!("Unknown lab in the calendar: {lab}");
+            }
+            let solution = saved_or_solved(&school, &rules, cli.backend, solution)?;
+            let bookings = calendar.bookings(&school, &rules.aliases, &solution, rules.weeks);
+            for booking in bookings.iter().filter(|b| b.closed.is_some()) {
+                let class: ClassRef = school.get(booking.class);
+                let lab: &Laboratory = school.get(booking.lab);
+                let slot: &Slot = school.get(booking.slot);
+                eprintln!(
+                    "Closed: {lab} on {} at {slot}, where {class} has a session",
+                    booking.date
+                );
+            }
+            export::calendar_html(&school, &rules.aliases, &calendar, &bookings, rules.weeks);
+        }
+        Command::Ics { dir, solution } => {
+            let calendar = read_calendar(&cli.calendar)?;
+            let solution = saved_or_solved(&school, &rules, cli.backend, solution)?;
+            let feeds = ics::feeds(&school, &calendar, &solution, rules.weeks);
+            fs::create_dir_all(&dir)?;
+            for feed in &feeds {
+                fs::write(dir.join(format!("{}.ics", feed.name)), &feed.contents)?;
+            }
+            eprintln!("Wrote {} calendars to {}", feeds.len(), dir.display());
+        }
+        Command::Validate => {
+            for diagnostic in &diagnostics {
+                println!("{diagnostic}");
+            }
+            for uneven in &uneven {
+                println!("{uneven}");
+            }
+            eprintln!("{} unknown names", diagnostics.len());
+            if !uneven.is_empty() {
+                eprintln!("{} classes that don't fit the weeks", uneven.len());
+            }
+        }
+        Command::Verify { solution } => {
+            let solution = load_saved(&school, solution)?;
+            let violations = verify(&school, &rules, &solution);
+            for violation in &violations {
+                println!("{}", violation.describe(&school));
+            }
+            eprintln!("{} violations", violations.len());
+        }
+    }
     Ok(())
 }
@@ -0,0 +1,57 @@
+use std::collections::BTreeMap;
+
+use eframe::egui::{self, Ui};
+use labs::rules::LabCapacity;
+
+use crate::list::List;
+
+/// Edit how many classes and students the lab takes at once
+pub fn lab(ui: &mut Ui, lab: &str, labs: &mut BTreeMap<String, LabCapacity>) {
+    let mut capacity = labs.remove(lab).unwrap_or_default();
+    ui.horizontal(|ui| {
+        ui.label("Turmas ao mesmo tempo");
+        ui.add(egui::DragValue::new(&mut capacity.classes).range(1..=8));
+        let mut limited = capacity.seats.is_some();
+        ui.checkbox(&mut limited, "Lugares");
+        match (limited, capacity.seats) {
+            (true, None) => capacity.seats = Some(30),
+            (false, Some(_)) => capacity.seats = None,
+            _ => {}
+        }
+        if let Some(seats) = &mut capacity.seats {
+            ui.add(egui::DragValue::new(seats).range(1..=200));
+        }
+    });
+    // Labs left as they are by default stay out of the rules
+    if capacity != LabCapacity::default() {
+        labs.insert(lab.to_string(), capacity);
+    }
+}
+
+/// Edit how many students each grade has
+pub fn sizes(ui: &mut Ui, sizes: &mut BTreeMap<String, usize>, grades: &[String]) {
+    // Names can't repeat, so a new size starts with a grade not yet listed
+    let new = (grades.iter())
+        .find(|g| !sizes.contains_key(*g))
+        .cloned()
+        .unwrap_or_default();
+    let mut list: Vec<(String, usize)> = std::mem::take(sizes).into_iter().collect();
+    List::new("Alunos por Turma").show_vec(
+        ui,
+        &mut list,
+        || (new.clone(), 30),
+        |ui, (i, (name, size))| {
+            ui.horizontal(|ui| {
+                egui::ComboBox::from_id_salt(format!("size_{i}"))
+                    .selected_text(name.clone())
+                    .show_ui(ui, |ui| {
+                        for grade in grades {
+                            ui.selectable_value(name, grade.clone(), grade);
+                        }
+                    });
+                ui.add(egui::DragValue::new(size).range(1..=200));
+            });
+        },
+    );
+    *sizes = list.into_iter().collect();
+}
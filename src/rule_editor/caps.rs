@@ -0,0 +1,61 @@
+use std::{collections::BTreeMap, iter};
+
+use eframe::egui::{self, Ui};
+use labs::rules::{Caps, ANY};
+
+use crate::list::List;
+
+fn limits(ui: &mut Ui, text: &str, id: &str, caps: &mut BTreeMap<String, usize>, names: &[String]) {
+    let options: Vec<&str> = iter::once(ANY)
+        .chain(names.iter().map(|n| n.as_str()))
+        .collect();
+    // Names can't repeat, so a new limit starts with one not yet used
+    let new = (options.iter())
+        .find(|o| !caps.contains_key(**o))
+        .unwrap_or(&ANY)
+        .to_string();
+    let mut limits: Vec<(String, usize)> = std::mem::take(caps).into_iter().collect();
+    List::new(text).show_vec(
+        ui,
+        &mut limits,
+        || (new.clone(), 1),
+        |ui, (i, (name, max))| {
+            ui.horizontal(|ui| {
+                egui::ComboBox::from_id_salt(format!("{id}_{i}"))
+                    .selected_text(name.clone())
+                    .show_ui(ui, |ui| {
+                        for option in &options {
+                            ui.selectable_value(name, option.to_string(), *option);
+                        }
+                    });
+                ui.add(egui::DragValue::new(max).range(0..=50));
+            });
+        },
+    );
+    *caps = limits.into_iter().collect();
+}
+
+/// Edit how many lab sessions each teacher, grade and lab may have
+pub fn show(ui: &mut Ui, caps: &mut Caps, teachers: &[String], grades: &[String], labs: &[String]) {
+    limits(
+        ui,
+        "Limite por Semana do Professor",
+        "cap_teacher",
+        &mut caps.teacher_per_week,
+        teachers,
+    );
+    limits(
+        ui,
+        "Limite por Dia da Turma",
+        "cap_grade",
+        &mut caps.grade_per_day,
+        grades,
+    );
+    limits(
+        ui,
+        "Limite por Dia do Laboratório",
+        "cap_lab",
+        &mut caps.lab_per_day,
+        labs,
+    );
+}
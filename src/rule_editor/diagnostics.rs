@@ -0,0 +1,52 @@
+use eframe::egui::{Color32, RichText, ScrollArea, Ui};
+use labs::validate::{Diagnostic, Kind, UnevenWeeks};
+
+fn kind(kind: Kind) -> &'static str {
+    match kind {
+        Kind::Teacher => "Professor",
+        Kind::Grade => "Turma",
+        Kind::Subject => "Matéria",
+        Kind::Lab => "Laboratório",
+        Kind::Class => "Aula",
+        Kind::Group => "Grupo",
+    }
+}
+
+/// List the names in the rules that are not in the school
+pub fn show(ui: &mut Ui, diagnostics: &[Diagnostic]) {
+    if diagnostics.is_empty() {
+        ui.label("Todos os nomes das regras existem no horário.");
+        return;
+    }
+    ui.label(format!(
+        "{} nomes não encontrados no horário:",
+        diagnostics.len()
+    ));
+    ScrollArea::vertical().max_height(120.).show(ui, |ui| {
+        for diagnostic in diagnostics {
+            let mut text = format!("{} não existe: {}", kind(diagnostic.kind), diagnostic.name);
+            if !diagnostic.location.is_empty() {
+                text += &format!(" (em {})", diagnostic.location.join(" > "));
+            }
+            if !diagnostic.suggestions.is_empty() {
+                text += &format!(". Você quis dizer {}?", diagnostic.suggestions.join(" ou "));
+            }
+            ui.label(RichText::new(text).color(Color32::from_rgb(170, 30, 50)));
+        }
+    });
+}
+
+/// List the classes that meet every few weeks when that doesn't fit the weeks in the cycle
+pub fn show_uneven(ui: &mut Ui, uneven: &[UnevenWeeks]) {
+    for uneven in uneven {
+        let fitting: Vec<_> = uneven.fitting().iter().map(|e| e.to_string()).collect();
+        let text = format!(
+            "Aula a cada {} semanas não cabe no ciclo de {} semanas (em {}). Use {}",
+            uneven.every,
+            uneven.weeks,
+            uneven.location.join(" > "),
+            fitting.join(", ")
+        );
+        ui.label(RichText::new(text).color(Color32::from_rgb(170, 30, 50)));
+    }
+}
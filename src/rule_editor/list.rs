@@ -105,9 +105,8 @@ where
     Inner: ApplyVecAction,
 {
     fn apply<T>(self, v: &mut Vec<T>, new_item: impl Fn() -> T) {
-        match self {
-            Some(action) => action.apply(v, new_item),
-            None => (),
+        if let Some(action) = self {
+            action.apply(v, new_item)
         }
     }
 }
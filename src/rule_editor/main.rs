@@ -1,7 +1,13 @@
+mod capacity;
+mod caps;
+mod diagnostics;
 mod list;
+mod solve;
+mod teachers;
 
 use std::{
-    collections::HashSet,
+    collections::{BTreeMap, HashSet},
+    iter,
     path::PathBuf,
     time::{Duration, Instant},
 };
@@ -10,8 +16,14 @@ use anyhow::Error;
 use eframe::egui;
 use egui::{Key, Modifiers};
 use egui_file_dialog::FileDialog;
-use labs::{rules::*, school::Time, sources::html::load_school};
+use labs::{
+    rules::*,
+    school::{School, Time},
+    sources::html::load_school,
+    validate::{uneven_weeks, validate},
+};
 use list::List;
+use solve::SolveTask;
 
 struct RulesApp {
     rules: Rules,
@@ -23,6 +35,12 @@ struct RulesApp {
     last_saved: Option<Result<Instant, Error>>,
     rules_path: PathBuf,
     file_dialog: FileDialog,
+    school: Option<School>,
+    solve: Option<SolveTask>,
+    /// Version of the rules file when it is older than the current format
+    outdated: Option<u32>,
+    /// Fields of the rules file that were dropped when reading it
+    unknown_fields: Vec<String>,
 }
 
 const RULES_PATH: &str = "input/rules.json";
@@ -31,13 +49,18 @@ const LABS_PATH: &str = "input/labs.json";
 
 impl RulesApp {
     fn new() -> Self {
+        let rules_path: PathBuf = RULES_PATH.into();
+        let loaded = read_rules(&rules_path).ok();
+        let rules = loaded.as_ref().map(|l| l.rules.clone()).unwrap_or_default();
+        let school = load_school(SCHOOL_PATH, LABS_PATH, &rules.aliases).ok();
         let (mut grades, mut teachers, mut subjects, mut times, mut labs): (
             Vec<_>,
             Vec<_>,
             Vec<_>,
             Vec<_>,
             Vec<_>,
-        ) = load_school(SCHOOL_PATH, LABS_PATH)
+        ) = school
+            .as_ref()
             .map(|school| {
                 (
                     school.grades().map(|grade| grade.name.to_owned()).collect(),
@@ -64,9 +87,8 @@ impl RulesApp {
         subjects.sort();
         times.sort();
         labs.sort();
-        let rules_path = RULES_PATH.into();
         Self {
-            rules: load_rules(&rules_path).unwrap_or_default(),
+            rules,
             rules_path,
             grades,
             teachers,
@@ -75,13 +97,22 @@ impl RulesApp {
             times,
             last_saved: None,
             file_dialog: FileDialog::new(),
+            school,
+            solve: None,
+            outdated: loaded
+                .as_ref()
+                .filter(|l| l.is_outdated())
+                .map(|l| l.version),
+            unknown_fields: loaded.map(|l| l.unknown_fields).unwrap_or_default(),
         }
     }
 
     fn load_rules(&mut self, new_path: PathBuf) {
-        match load_rules(&new_path) {
-            Ok(rules) => {
-                self.rules = rules;
+        match read_rules(&new_path) {
+            Ok(loaded) => {
+                self.outdated = loaded.is_outdated().then_some(loaded.version);
+                self.unknown_fields = loaded.unknown_fields;
+                self.rules = loaded.rules;
                 self.last_saved = None;
                 self.rules_path = new_path;
             }
@@ -92,7 +123,36 @@ impl RulesApp {
     }
 
     fn save_rules(&mut self) {
-        self.last_saved = Some(save_rules(&self.rules, RULES_PATH).map(|_| Instant::now()))
+        let saved = save_rules(&self.rules, &self.rules_path);
+        if saved.is_ok() {
+            self.outdated = None;
+            self.unknown_fields.clear();
+        }
+        self.last_saved = Some(saved.map(|_| Instant::now()))
+    }
+
+    /// Offer to upgrade an old rules file and list what was dropped when reading it
+    fn show_format(&mut self, ui: &mut egui::Ui) {
+        if let Some(version) = self.outdated {
+            ui.label(format!(
+                "Arquivo no formato antigo (versão {version}, atual {VERSION})."
+            ));
+            if ui.button("Salvar no formato novo").clicked() {
+                self.save_rules();
+            }
+        }
+        if !self.unknown_fields.is_empty() {
+            ui.label(format!(
+                "Campos desconhecidos ignorados: {}",
+                self.unknown_fields.join(", ")
+            ));
+        }
+    }
+
+    fn start_solve(&mut self, ctx: &egui::Context) {
+        if let Some(school) = &self.school {
+            self.solve = Some(SolveTask::spawn(ctx, school.clone(), self.rules.clone()));
+        }
     }
 }
 
@@ -132,17 +192,41 @@ impl eframe::App for RulesApp {
                             }
                         }
                     }
+                    let running = self.solve.as_ref().is_some_and(|s| s.is_running());
+                    if ui
+                        .add_enabled(
+                            self.school.is_some() && !running,
+                            egui::Button::new("▶ Resolver"),
+                        )
+                        .clicked()
+                    {
+                        self.start_solve(ctx);
+                    }
+                    if let Some(solve) = &self.solve {
+                        solve.show(ui);
+                    }
+                    self.show_format(ui);
                     self.file_dialog.update(ctx);
                     if let Some(path) = self.file_dialog.take_picked() {
                         self.load_rules(path)
                     }
                 })
             });
+        if let Some(school) = &self.school {
+            egui::TopBottomPanel::bottom("diagnostics").show(ctx, |ui| {
+                diagnostics::show(ui, &validate(school, &self.rules));
+                diagnostics::show_uneven(ui, &uneven_weeks(&self.rules));
+            });
+        }
         egui::CentralPanel::default().show(ctx, |ui| {
             egui::ScrollArea::vertical().show(ui, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("Semanas no ciclo");
+                    ui.add(egui::DragValue::new(&mut self.rules.weeks).range(1..=8));
+                });
                 for lab in self.labs.iter() {
                     ui.heading(lab);
-                    let times = self.rules.forbidden_times.entry(lab.clone()).or_insert_with(|| vec![]);
+                    let times = self.rules.forbidden_times.entry(lab.clone()).or_default();
                     List::new("Horário Proibido").show_vec(ui, times, || *self.times.first().unwrap_or(&Time(0,0)), |ui, (time_idx, time)| {
                         egui::ComboBox::from_id_salt(format!("time_{lab}_{time_idx}"))
                             .selected_text(time.to_string())
@@ -156,8 +240,35 @@ impl eframe::App for RulesApp {
                                 }
                             });
                     });
+                    capacity::lab(ui, lab, &mut self.rules.labs);
                 }
+                capacity::sizes(ui, &mut self.rules.sizes, &self.grades);
+                ui.heading("Professores");
+                teachers::show(ui, &mut self.rules.teachers, &self.teachers, &self.times);
+                ui.heading("Limites");
+                caps::show(
+                    ui,
+                    &mut self.rules.caps,
+                    &self.teachers,
+                    &self.grades,
+                    &self.labs,
+                );
                 ui.heading("Aulas");
+                let group_names = |groups: &BTreeMap<String, Vec<String>>| -> Vec<String> {
+                    groups.keys().map(|g| format!("{GROUP}{g}")).collect()
+                };
+                let teachers: Vec<_> = iter::once(ANY.to_string())
+                    .chain(self.teachers.iter().cloned())
+                    .collect();
+                let grades: Vec<_> = iter::once(ANY.to_string())
+                    .chain(group_names(&self.rules.groups.grades))
+                    .chain(self.grades.iter().cloned())
+                    .collect();
+                let weeks = self.rules.weeks;
+                let labs: Vec<_> = group_names(&self.rules.groups.labs)
+                    .into_iter()
+                    .chain(self.labs.iter().cloned())
+                    .collect();
                 List::new("Matéria").wide().show_vec_default(
                     ui,
                     &mut self.rules.classes,
@@ -191,7 +302,7 @@ impl eframe::App for RulesApp {
                                         .show_ui(
                                             ui,
                                             |ui| {
-                                                for option in &self.teachers {
+                                                for option in &teachers {
                                                     ui.selectable_value(
                                                         &mut teacher.name,
                                                         option.to_string(),
@@ -221,7 +332,7 @@ impl eframe::App for RulesApp {
                                                 ))
                                                 .selected_text(grade.name.clone())
                                                 .show_ui(ui, |ui| {
-                                                    for option in &self.grades {
+                                                    for option in &grades {
                                                         ui.selectable_value(
                                                             &mut grade.name,
                                                             option.to_string(),
@@ -229,6 +340,45 @@ impl eframe::App for RulesApp {
                                                         );
                                                     }
                                                 });
+                                                ui.checkbox(&mut grade.double, "Aula dupla");
+                                                if weeks > 1 {
+                                                    ui.label("A cada");
+                                                    ui.add(
+                                                        egui::DragValue::new(&mut grade.every)
+                                                            .range(1..=weeks),
+                                                    );
+                                                    ui.label("semanas");
+                                                }
+                                                let mut split = grade.split.is_some();
+                                                if ui
+                                                    .checkbox(&mut split, "Dividir turma")
+                                                    .changed()
+                                                {
+                                                    grade.split = split.then(Split::default);
+                                                }
+                                                if let Some(split) = &mut grade.split {
+                                                    let same = "Mesmo professor";
+                                                    egui::ComboBox::from_id_salt(format!(
+                                                        "split_{class_idx}_{teacher_idx}_{i}"
+                                                    ))
+                                                    .selected_text(
+                                                    split.teacher.as_deref().unwrap_or(same),
+                                                )
+                                                    .show_ui(ui, |ui| {
+                                                        ui.selectable_value(
+                                                        &mut split.teacher,
+                                                        None,
+                                                        same,
+                                                    );
+                                                        for option in &self.teachers {
+                                                            ui.selectable_value(
+                                                                &mut split.teacher,
+                                                                Some(option.to_string()),
+                                                                option,
+                                                            );
+                                                        }
+                                                    });
+                                                }
                                                 let new = grade
                                                 .labs
                                                 .last()
@@ -245,7 +395,7 @@ impl eframe::App for RulesApp {
                                                         ))
                                                         .selected_text(lab.clone())
                                                         .show_ui(ui, |ui| {
-                                                            for option in &self.labs {
+                                                            for option in &labs {
                                                                 ui.selectable_value(
                                                                     lab,
                                                                     option.to_string(),
@@ -0,0 +1,92 @@
+use std::{
+    sync::{Arc, Mutex},
+    thread,
+};
+
+use eframe::egui::{Context, Ui};
+use labs::{
+    rules::Rules,
+    school::School,
+    solver::{cancel::CancelToken, solution::Statistics, Solver},
+};
+
+enum Status {
+    Running(Statistics),
+    Done {
+        statistics: Statistics,
+        slotted: usize,
+        errors: usize,
+        warnings: usize,
+        cancelled: bool,
+    },
+}
+
+/// A solve running in the background
+pub struct SolveTask {
+    status: Arc<Mutex<Status>>,
+    cancel: CancelToken,
+}
+
+impl SolveTask {
+    pub fn spawn(ctx: &Context, school: School, rules: Rules) -> Self {
+        let status = Arc::new(Mutex::new(Status::Running(Statistics::default())));
+        let shared = status.clone();
+        let ctx = ctx.clone();
+        let cancel = CancelToken::new();
+        let token = cancel.clone();
+        thread::spawn(move || {
+            let solution = Solver::new(&school, &rules)
+                .cancel_with(token)
+                .on_progress(|statistics| {
+                    *shared.lock().unwrap() = Status::Running(statistics.clone());
+                    ctx.request_repaint();
+                })
+                .solve();
+            *shared.lock().unwrap() = Status::Done {
+                statistics: solution.statistics,
+                slotted: solution.slotted.len(),
+                errors: solution.errors.len(),
+                warnings: solution.warnings.len(),
+                cancelled: solution.cancelled,
+            };
+            ctx.request_repaint();
+        });
+        Self { status, cancel }
+    }
+
+    pub fn is_running(&self) -> bool {
+        matches!(*self.status.lock().unwrap(), Status::Running(_))
+    }
+
+    pub fn cancel(&self) {
+        self.cancel.cancel();
+    }
+
+    pub fn show(&self, ui: &mut Ui) {
+        match &*self.status.lock().unwrap() {
+            Status::Running(statistics) => {
+                ui.spinner();
+                ui.label(format!(
+                    "Resolvendo: {} estados, {} retrocessos, {} opções de laboratório",
+                    statistics.nodes, statistics.backtracks, statistics.relaxations
+                ));
+                if ui.button("⏹ Cancelar").clicked() {
+                    self.cancel();
+                }
+            }
+            Status::Done {
+                statistics,
+                slotted,
+                errors,
+                warnings,
+                cancelled,
+            } => {
+                let verb = if *cancelled { "Cancelado" } else { "Resolvido" };
+                ui.label(format!(
+                    "{verb} em {:.1}s: {slotted} aulas alocadas, {errors} erros, {warnings} avisos",
+                    statistics.elapsed.as_secs_f32()
+                ));
+            }
+        }
+    }
+}
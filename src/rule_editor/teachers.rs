@@ -0,0 +1,92 @@
+use eframe::egui::{self, Ui};
+use labs::{
+    rules::{Period, TeacherTimes},
+    school::{Day, Time, DAYS},
+};
+
+use crate::list::List;
+
+fn day_name(day: Option<Day>) -> &'static str {
+    match day {
+        None => "Todos os dias",
+        Some(Day::Monday) => "Segunda",
+        Some(Day::Tuesday) => "Terça",
+        Some(Day::Wednesday) => "Quarta",
+        Some(Day::Thursday) => "Quinta",
+        Some(Day::Friday) => "Sexta",
+    }
+}
+
+fn periods(ui: &mut Ui, text: &str, id: &str, periods: &mut Vec<Period>, times: &[Time]) {
+    let first = *times.first().unwrap_or(&Time(0, 0));
+    let new = Period {
+        day: None,
+        from: first,
+        to: first,
+    };
+    List::new(text).show_vec(
+        ui,
+        periods,
+        || new,
+        |ui, (i, period)| {
+            ui.horizontal(|ui| {
+                egui::ComboBox::from_id_salt(format!("{id}_day_{i}"))
+                    .selected_text(day_name(period.day))
+                    .show_ui(ui, |ui| {
+                        for day in [None].into_iter().chain(DAYS.map(Some)) {
+                            ui.selectable_value(&mut period.day, day, day_name(day));
+                        }
+                    });
+                ui.label("das");
+                egui::ComboBox::from_id_salt(format!("{id}_from_{i}"))
+                    .selected_text(period.from.to_string())
+                    .show_ui(ui, |ui| {
+                        for option in times {
+                            ui.selectable_value(&mut period.from, *option, option.to_string());
+                        }
+                    });
+                ui.label("às");
+                egui::ComboBox::from_id_salt(format!("{id}_to_{i}"))
+                    .selected_text(period.to.to_string())
+                    .show_ui(ui, |ui| {
+                        for option in times.iter().filter(|&&t| t >= period.from) {
+                            ui.selectable_value(&mut period.to, *option, option.to_string());
+                        }
+                    });
+                period.to = period.to.max(period.from);
+            });
+        },
+    );
+}
+
+/// Edit the times each teacher can't or would rather have lab classes at
+pub fn show(ui: &mut Ui, teachers: &mut Vec<TeacherTimes>, names: &[String], times: &[Time]) {
+    List::new("Professor")
+        .wide()
+        .show_vec_default(ui, teachers, |ui, (i, teacher)| {
+            ui.horizontal(|ui| {
+                ui.label("Prof.");
+                egui::ComboBox::from_id_salt(format!("teacher_times_{i}"))
+                    .selected_text(teacher.name.clone())
+                    .show_ui(ui, |ui| {
+                        for option in names {
+                            ui.selectable_value(&mut teacher.name, option.to_string(), option);
+                        }
+                    });
+                periods(
+                    ui,
+                    "Horário Indisponível",
+                    &format!("unavailable_{i}"),
+                    &mut teacher.unavailable,
+                    times,
+                );
+                periods(
+                    ui,
+                    "Horário Preferido",
+                    &format!("preferred_{i}"),
+                    &mut teacher.preferred,
+                    times,
+                );
+            });
+        });
+}
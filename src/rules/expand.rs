@@ -0,0 +1,230 @@
+use std::collections::BTreeMap;
+
+use super::{Rules, Split};
+use crate::school::School;
+
+/// Matches every teacher or grade
+pub const ANY: &str = "*";
+
+/// Prefix of a reference to a named group
+pub const GROUP: char = '@';
+
+/// What a teacher or grade name in the rules refers to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pattern<'a> {
+    /// A single name
+    Exact(&'a str),
+    /// Names matching a glob, where `*` is any run of characters and `?` any one character
+    Glob(&'a str),
+    /// The members of a named group
+    Group(&'a str),
+}
+
+impl<'a> Pattern<'a> {
+    pub fn parse(name: &'a str) -> Self {
+        if let Some(group) = name.strip_prefix(GROUP) {
+            Pattern::Group(group)
+        } else if name.contains(['*', '?']) {
+            Pattern::Glob(name)
+        } else {
+            Pattern::Exact(name)
+        }
+    }
+
+    pub fn is_exact(&self) -> bool {
+        matches!(self, Pattern::Exact(_))
+    }
+}
+
+fn glob(pattern: &[char], name: &[char]) -> bool {
+    match (pattern.first(), name.first()) {
+        (None, None) => true,
+        (Some('*'), _) => {
+            glob(&pattern[1..], name) || (!name.is_empty() && glob(pattern, &name[1..]))
+        }
+        (Some('?'), Some(_)) => glob(&pattern[1..], &name[1..]),
+        (Some(p), Some(n)) if p == n => glob(&pattern[1..], &name[1..]),
+        _ => false,
+    }
+}
+
+/// A single class of the rules, after expanding wildcards and groups
+#[derive(Debug, Clone)]
+pub struct Expanded<'a> {
+    pub teacher: &'a str,
+    pub grade: &'a str,
+    pub subject: &'a str,
+    /// Lab choices in order of preference, with groups expanded
+    pub labs: Vec<&'a str>,
+    pub double: bool,
+    pub every: usize,
+    pub split: Option<&'a Split>,
+}
+
+fn matches(pattern: Pattern, name: &str, groups: &BTreeMap<String, Vec<String>>) -> bool {
+    match pattern {
+        Pattern::Exact(exact) => exact == name,
+        Pattern::Glob(pattern) => {
+            let pattern: Vec<char> = pattern.chars().collect();
+            let name: Vec<char> = name.chars().collect();
+            glob(&pattern, &name)
+        }
+        Pattern::Group(group) => groups
+            .get(group)
+            .is_some_and(|members| members.iter().any(|m| m == name)),
+    }
+}
+
+impl Rules {
+    /// The labs in a list of lab choices, with groups replaced by their members
+    pub fn expand_labs<'a>(&'a self, labs: &'a [String]) -> Vec<&'a str> {
+        let mut expanded = vec![];
+        for lab in labs {
+            let members = match Pattern::parse(lab) {
+                Pattern::Group(group) => self
+                    .groups
+                    .labs
+                    .get(group)
+                    .map(|members| members.iter().map(|m| m.as_str()).collect())
+                    .unwrap_or_default(),
+                _ => vec![lab.as_str()],
+            };
+            for member in members {
+                if !expanded.contains(&member) {
+                    expanded.push(member);
+                }
+            }
+        }
+        expanded
+    }
+
+    /// Every class the rules refer to, finding the ones behind wildcards and groups in the
+    /// school. When a class is matched more than once, the rule naming it most precisely wins,
+    /// then the first one.
+    pub fn expand<'a>(&'a self, school: &'a School) -> Vec<Expanded<'a>> {
+        let aliases = &self.aliases;
+        let mut expanded: Vec<(usize, Expanded)> = vec![];
+        let mut seen: BTreeMap<(String, String, String), usize> = BTreeMap::new();
+        let mut add = |precision: usize, class: Expanded<'a>| {
+            let key = (
+                aliases.teacher(class.teacher),
+                aliases.grade(class.grade),
+                aliases.subject(class.subject),
+            );
+            match seen.get(&key) {
+                Some(&i) if expanded[i].0 >= precision => {}
+                Some(&i) => expanded[i] = (precision, class),
+                None => {
+                    seen.insert(key, expanded.len());
+                    expanded.push((precision, class));
+                }
+            }
+        };
+        // Teachers can't be grouped
+        let no_groups = BTreeMap::new();
+        let grade_groups: BTreeMap<String, Vec<String>> = (self.groups.grades.iter())
+            .map(|(name, members)| {
+                (
+                    name.clone(),
+                    members.iter().map(|m| aliases.grade(m)).collect(),
+                )
+            })
+            .collect();
+        for class in &self.classes {
+            let subject = aliases.subject(&class.subject);
+            for teacher in &class.teachers {
+                let teacher_name = aliases.teacher(&teacher.name);
+                let teacher_pattern = Pattern::parse(&teacher_name);
+                for grade in &teacher.grades {
+                    let grade_name = aliases.grade(&grade.name);
+                    let grade_pattern = Pattern::parse(&grade_name);
+                    let labs = self.expand_labs(&grade.labs);
+                    let precision = usize::from(teacher_pattern.is_exact())
+                        + usize::from(grade_pattern.is_exact());
+                    if precision == 2 {
+                        add(
+                            precision,
+                            Expanded {
+                                teacher: &teacher.name,
+                                grade: &grade.name,
+                                subject: &class.subject,
+                                labs,
+                                double: grade.double,
+                                every: grade.every,
+                                split: grade.split.as_ref(),
+                            },
+                        );
+                        continue;
+                    }
+                    for found in school.classes() {
+                        let matches = found.subject.name == subject
+                            && matches(teacher_pattern, &found.teacher.name, &no_groups)
+                            && matches(grade_pattern, &found.grade.name, &grade_groups);
+                        if matches {
+                            add(
+                                precision,
+                                Expanded {
+                                    teacher: &found.teacher.name,
+                                    grade: &found.grade.name,
+                                    subject: &found.subject.name,
+                                    labs: labs.clone(),
+                                    double: grade.double,
+                                    every: grade.every,
+                                    split: grade.split.as_ref(),
+                                },
+                            );
+                        }
+                    }
+                }
+            }
+        }
+        expanded.into_iter().map(|(_, class)| class).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::{school::Day::Monday, testing};
+
+    #[test]
+    fn expands_globs_groups_and_wildcards() {
+        let school = testing::school(
+            &[
+                ("1A", "Química", "Ana", Monday, "7:30"),
+                ("1B", "Química", "Bia", Monday, "7:30"),
+                ("2A", "Química", "Ana", Monday, "8:20"),
+                ("2B", "Física", "Ana", Monday, "9:10"),
+            ],
+            &["L1", "L2", "L3"],
+        );
+        let rules = testing::rules(json!({
+            "classes": [
+                {"subject": "Química", "teachers": [
+                    {"name": "*", "grades": [{"name": "1?", "labs": ["@fundos", "L1"]}]},
+                    {"name": "Ana", "grades": [{"name": "1A", "labs": ["L3"]}]},
+                ]},
+                {"subject": "Física", "teachers": [
+                    {"name": "A*", "grades": [{"name": "@segundos", "labs": ["L3"]}]},
+                ]},
+            ],
+            "groups": {
+                "grades": {"segundos": ["2A", "2B"]},
+                "labs": {"fundos": ["L2", "L3"]},
+            },
+        }));
+        let mut expanded: Vec<_> = (rules.expand(&school).into_iter())
+            .map(|class| (class.teacher, class.grade, class.subject, class.labs))
+            .collect();
+        expanded.sort();
+        assert_eq!(
+            expanded,
+            [
+                ("Ana", "1A", "Química", vec!["L3"]),
+                ("Ana", "2B", "Física", vec!["L3"]),
+                ("Bia", "1B", "Química", vec!["L2", "L3", "L1"]),
+            ]
+        );
+    }
+}
@@ -0,0 +1,70 @@
+use anyhow::bail;
+use serde_json::{Map, Value};
+
+/// The version of the rules file written by this program. Files from before versioning are
+/// version 1.
+pub const VERSION: u32 = 2;
+
+/// Upgrades a file from the version at its index plus one to the next version
+const MIGRATIONS: [fn(&mut Map<String, Value>); VERSION as usize - 1] = [v1_to_v2];
+
+/// Version 2 only added the version field itself
+fn v1_to_v2(_rules: &mut Map<String, Value>) {}
+
+/// Take the version out of a rules file and upgrade the rest to the current version,
+/// returning the version it had
+pub fn migrate(rules: &mut Value) -> anyhow::Result<u32> {
+    let Value::Object(rules) = rules else {
+        bail!("The rules file must contain an object");
+    };
+    let version = match rules.remove("version") {
+        None => 1,
+        Some(Value::Number(n)) => match n.as_u64() {
+            Some(n @ 1..) => n as u32,
+            _ => bail!("Bad rules file version: {n}"),
+        },
+        Some(other) => bail!("Bad rules file version: {other}"),
+    };
+    if version > VERSION {
+        bail!("The rules file is from a newer version ({version}) than this program supports ({VERSION})");
+    }
+    for migration in &MIGRATIONS[version as usize - 1..] {
+        migration(rules);
+    }
+    Ok(version)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::rules::{Rules, Versioned};
+
+    #[test]
+    fn upgrades_version_1_and_saves_it_back_the_same() {
+        let v1 = json!({
+            "classes": [{"subject": "Química", "teachers": [
+                {"name": "Ana", "grades": [{"name": "1A", "labs": ["L1", "L2"]}]},
+            ]}],
+            "forbidden_times": {"L1": ["07:30"]},
+        });
+        let mut value = v1.clone();
+        assert_eq!(migrate(&mut value).unwrap(), 1);
+        let rules: Rules = serde_json::from_value(value).unwrap();
+        let versioned = Versioned {
+            version: VERSION,
+            rules: &rules,
+        };
+        let mut saved = serde_json::to_value(versioned).unwrap();
+        assert_eq!(saved["version"], VERSION);
+        assert_eq!(migrate(&mut saved).unwrap(), VERSION);
+        assert_eq!(saved, v1);
+    }
+
+    #[test]
+    fn rejects_newer_versions() {
+        let mut value = json!({"version": VERSION + 1, "classes": [], "forbidden_times": {}});
+        assert!(migrate(&mut value).is_err());
+    }
+}
@@ -0,0 +1,311 @@
+mod expand;
+mod migrate;
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::{self, File},
+    io::BufReader,
+    path::Path,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    school::{Day, School, Slot, Time},
+    validate::fold,
+};
+pub use expand::{Expanded, Pattern, ANY, GROUP};
+pub use migrate::VERSION;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Rules {
+    pub classes: Vec<ClassRules>,
+    pub forbidden_times: HashMap<String, Vec<Time>>,
+    #[serde(default, skip_serializing_if = "Aliases::is_empty")]
+    pub aliases: Aliases,
+    #[serde(default, skip_serializing_if = "Groups::is_empty")]
+    pub groups: Groups,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub teachers: Vec<TeacherTimes>,
+    #[serde(default, skip_serializing_if = "Caps::is_empty")]
+    pub caps: Caps,
+    /// How many weeks the schedule takes to repeat, named A, B and so on
+    #[serde(default = "one", skip_serializing_if = "is_one")]
+    pub weeks: usize,
+    /// How many classes and students each lab takes at once, one class when not listed
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub labs: BTreeMap<String, LabCapacity>,
+    /// Students in each grade, to check that they fit in the labs
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub sizes: BTreeMap<String, usize>,
+}
+
+/// How many classes and students a lab takes at once
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct LabCapacity {
+    /// Classes the lab takes at once
+    #[serde(default = "one")]
+    pub classes: usize,
+    /// Students the lab seats, shared by all of the classes in it at once
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seats: Option<usize>,
+}
+
+impl Default for LabCapacity {
+    fn default() -> Self {
+        Self {
+            classes: 1,
+            seats: None,
+        }
+    }
+}
+
+impl Default for Rules {
+    fn default() -> Self {
+        Self {
+            classes: vec![],
+            forbidden_times: HashMap::new(),
+            aliases: Aliases::default(),
+            groups: Groups::default(),
+            teachers: vec![],
+            caps: Caps::default(),
+            weeks: 1,
+            labs: BTreeMap::new(),
+            sizes: BTreeMap::new(),
+        }
+    }
+}
+
+fn one() -> usize {
+    1
+}
+
+fn is_one(n: &usize) -> bool {
+    *n == 1
+}
+
+/// The most lab sessions allowed for each name, where `*` applies to every name not listed.
+/// A double session counts once.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Caps {
+    /// Sessions of each teacher in a week
+    pub teacher_per_week: BTreeMap<String, usize>,
+    /// Sessions of each grade in a day
+    pub grade_per_day: BTreeMap<String, usize>,
+    /// Sessions in each lab in a day
+    pub lab_per_day: BTreeMap<String, usize>,
+}
+
+impl Caps {
+    pub fn is_empty(&self) -> bool {
+        self.teacher_per_week.is_empty()
+            && self.grade_per_day.is_empty()
+            && self.lab_per_day.is_empty()
+    }
+}
+
+/// When a teacher can or would rather have lab classes
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct TeacherTimes {
+    pub name: String,
+    /// Times the teacher's classes are never placed at
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub unavailable: Vec<Period>,
+    /// Times the teacher's classes are placed at when possible. Any time is fine when empty.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub preferred: Vec<Period>,
+}
+
+/// The times from one to another, both included, on one day or on every day when the day is
+/// missing
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Period {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub day: Option<Day>,
+    pub from: Time,
+    pub to: Time,
+}
+
+impl Period {
+    pub fn contains(&self, slot: &Slot) -> bool {
+        self.day.is_none_or(|day| day == slot.day) && (self.from..=self.to).contains(&slot.time)
+    }
+}
+
+/// Named lists of grades or labs, referred to in the rules as `@name`
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Groups {
+    pub grades: BTreeMap<String, Vec<String>>,
+    pub labs: BTreeMap<String, Vec<String>>,
+}
+
+impl Groups {
+    pub fn is_empty(&self) -> bool {
+        self.grades.is_empty() && self.labs.is_empty()
+    }
+}
+
+/// Alternate spellings of names, each mapped to the one used everywhere else. Spellings are
+/// matched ignoring accents, case and the kind of spaces.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Aliases {
+    pub teachers: BTreeMap<String, String>,
+    pub grades: BTreeMap<String, String>,
+    pub subjects: BTreeMap<String, String>,
+    pub labs: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct ClassRules {
+    pub subject: String,
+    pub teachers: Vec<TeacherRules>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct TeacherRules {
+    /// A teacher, or `*` for every teacher of the subject
+    pub name: String,
+    pub grades: Vec<GradeRules>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct GradeRules {
+    /// A grade, a glob like `1º*` or a group like `@primeiros`
+    pub name: String,
+    /// Labs or groups of labs, in order of preference
+    pub labs: Vec<String>,
+    /// Take two adjacent periods on the same day, in the same lab
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub double: bool,
+    /// Meet once every this many weeks, 2 being every other week
+    #[serde(default = "one", skip_serializing_if = "is_one")]
+    pub every: usize,
+    /// Split the grade into two half-groups, each in a lab of its own at the same time
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub split: Option<Split>,
+}
+
+/// How a grade is split into two half-groups
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct Split {
+    /// Teacher of the second half-group, when not the same as the first
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub teacher: Option<String>,
+}
+
+impl Default for GradeRules {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            labs: vec![],
+            double: false,
+            every: 1,
+            split: None,
+        }
+    }
+}
+
+fn canonical(aliases: &BTreeMap<String, String>, name: &str) -> String {
+    let folded = fold(name);
+    aliases
+        .iter()
+        .find(|(alias, _)| fold(alias) == folded)
+        .map(|(_, canonical)| canonical.clone())
+        .unwrap_or_else(|| name.to_string())
+}
+
+impl Aliases {
+    pub fn is_empty(&self) -> bool {
+        self.teachers.is_empty()
+            && self.grades.is_empty()
+            && self.subjects.is_empty()
+            && self.labs.is_empty()
+    }
+
+    pub fn teacher(&self, name: &str) -> String {
+        canonical(&self.teachers, name)
+    }
+
+    pub fn grade(&self, name: &str) -> String {
+        canonical(&self.grades, name)
+    }
+
+    pub fn subject(&self, name: &str) -> String {
+        canonical(&self.subjects, name)
+    }
+
+    pub fn lab(&self, name: &str) -> String {
+        canonical(&self.labs, name)
+    }
+}
+
+impl Rules {
+    /// The teacher, grade and subject of every class in the rules, with wildcards and groups
+    /// expanded against the school
+    pub fn flatten<'a>(
+        &'a self,
+        school: &'a School,
+    ) -> impl Iterator<Item = (&'a str, &'a str, &'a str)> {
+        self.expand(school)
+            .into_iter()
+            .map(|class| (class.teacher, class.grade, class.subject))
+    }
+}
+
+/// Rules read from a file, with what had to be changed to read them
+#[derive(Debug, Clone)]
+pub struct LoadedRules {
+    pub rules: Rules,
+    /// The version the file was saved in
+    pub version: u32,
+    /// Paths of the fields in the file that are not part of the rules, which were dropped
+    pub unknown_fields: Vec<String>,
+}
+
+impl LoadedRules {
+    /// Whether saving would change the format of the file
+    pub fn is_outdated(&self) -> bool {
+        self.version < VERSION
+    }
+}
+
+/// How the rules are written, with the version first
+#[derive(Serialize)]
+struct Versioned<'a> {
+    version: u32,
+    #[serde(flatten)]
+    rules: &'a Rules,
+}
+
+/// Read rules saved in any version, upgrading them to the current one
+pub fn read_rules(p: impl AsRef<Path>) -> anyhow::Result<LoadedRules> {
+    let file = File::open(p)?;
+    let reader = BufReader::new(file);
+    let mut value: serde_json::Value = serde_json::from_reader(reader)?;
+    let version = migrate::migrate(&mut value)?;
+    let mut unknown_fields = vec![];
+    let rules = serde_ignored::deserialize(value, |path| unknown_fields.push(path.to_string()))?;
+    Ok(LoadedRules {
+        rules,
+        version,
+        unknown_fields,
+    })
+}
+
+pub fn load_rules(p: impl AsRef<Path>) -> anyhow::Result<Rules> {
+    Ok(read_rules(p)?.rules)
+}
+
+pub fn save_rules(rules: &Rules, p: impl AsRef<Path>) -> anyhow::Result<()> {
+    let p = p.as_ref();
+    let s = serde_json::to_string_pretty(&Versioned {
+        version: VERSION,
+        rules,
+    })?;
+    fs::create_dir_all("input")?;
+    fs::write(p, s)?;
+    Ok(())
+}
@@ -1,7 +1,8 @@
 use crate::table::Table;
+use serde::{Deserialize, Serialize};
 use std::{fmt::Display, str::FromStr};
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
 pub enum Day {
     Monday,
     Tuesday,
@@ -21,7 +22,7 @@ pub const DAYS: [Day; 5] = [
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
 pub struct Time(pub u8, pub u8);
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
 pub struct Slot {
     pub day: Day,
     pub time: Time,
@@ -110,10 +111,10 @@ impl School {
     pub fn subjects(&self) -> impl Iterator<Item = &Subject> {
         self.iter()
     }
-    pub fn classes(&self) -> impl Iterator<Item = ClassRef> {
+    pub fn classes(&self) -> impl Iterator<Item = ClassRef<'_>> {
         self.iter()
     }
-    pub fn slotted_classes(&self) -> impl Iterator<Item = SlottedClassRef> {
+    pub fn slotted_classes(&self) -> impl Iterator<Item = SlottedClassRef<'_>> {
         self.iter()
     }
     pub fn labs(&self) -> impl Iterator<Item = &Laboratory> {
@@ -159,12 +160,31 @@ impl School {
             },
         )
     }
+    /// Whether the teacher has a class at the slot
+    pub fn teaches_at(&self, teacher: usize, slot: usize) -> bool {
+        (self.slotted_classes.iter()).any(|(_, sc)| {
+            sc.slot == slot
+                && self
+                    .classes
+                    .get(sc.class)
+                    .is_some_and(|c| c.teacher == teacher)
+        })
+    }
+    /// The slot right after this one on the same day, if any
+    pub fn next_slot(&self, slot_id: usize) -> Option<usize> {
+        let slot: &Slot = self.get(slot_id);
+        self.slots
+            .iter()
+            .filter(|(_, other)| other.day == slot.day && other.time > slot.time)
+            .min_by_key(|(_, other)| other.time)
+            .map(|(&id, _)| id)
+    }
 }
 
 impl FromStr for Time {
     type Err = &'static str;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let mut time = s.split(":").map(|x| u8::from_str_radix(x, 10));
+        let mut time = s.split(":").map(|x| x.parse::<u8>());
         match (time.next(), time.next(), time.next()) {
             (Some(Ok(h)), Some(Ok(m)), None) if h <= 23 && m <= 59 => Ok(Time(h, m)),
             _ => Err("Bad time format"),
@@ -0,0 +1,222 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::{
+    rules::Rules,
+    school::{ClassRef, School, Slot},
+    solver::saved::SavedClass,
+};
+
+/// Names found in only one of two schools
+#[derive(Debug, Clone, Default)]
+pub struct Changes<T> {
+    pub added: Vec<T>,
+    pub removed: Vec<T>,
+}
+
+impl<T: Ord + Clone> Changes<T> {
+    fn between(before: BTreeSet<T>, after: BTreeSet<T>) -> Self {
+        Self {
+            added: after.difference(&before).cloned().collect(),
+            removed: before.difference(&after).cloned().collect(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty()
+    }
+}
+
+/// A class that meets at different slots in the new school
+#[derive(Debug, Clone)]
+pub struct MovedClass {
+    pub class: SavedClass,
+    pub from: Vec<Slot>,
+    pub to: Vec<Slot>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Reason {
+    TeacherRemoved,
+    GradeRemoved,
+    SubjectRemoved,
+    ClassRemoved,
+    ClassMoved,
+    LabRemoved(String),
+}
+
+/// A class in the rules that needs another look after the school changed
+#[derive(Debug, Clone)]
+pub struct AffectedRule {
+    pub class: SavedClass,
+    pub reason: Reason,
+}
+
+/// What changed between two imports of the school, matching everything by name
+#[derive(Debug, Clone)]
+pub struct SchoolDiff {
+    pub teachers: Changes<String>,
+    pub grades: Changes<String>,
+    pub subjects: Changes<String>,
+    pub labs: Changes<String>,
+    pub classes: Changes<SavedClass>,
+    pub moved: Vec<MovedClass>,
+}
+
+fn class_name(class: ClassRef) -> SavedClass {
+    SavedClass {
+        teacher: class.teacher.name.clone(),
+        grade: class.grade.name.clone(),
+        subject: class.subject.name.clone(),
+    }
+}
+
+fn names<'a>(names: impl Iterator<Item = &'a String>) -> BTreeSet<String> {
+    names.cloned().collect()
+}
+
+/// Every class with the slots it meets at
+fn class_slots(school: &School) -> BTreeMap<SavedClass, Vec<Slot>> {
+    school
+        .classes
+        .keys()
+        .map(|&id| {
+            let mut slots: Vec<Slot> = school
+                .slots_of(id)
+                .map(|slot| school.get::<&Slot>(slot).clone())
+                .collect();
+            slots.sort();
+            (class_name(school.get(id)), slots)
+        })
+        .collect()
+}
+
+impl SchoolDiff {
+    pub fn new(before: &School, after: &School) -> Self {
+        let (old_classes, new_classes) = (class_slots(before), class_slots(after));
+        let moved = old_classes
+            .iter()
+            .filter_map(|(class, from)| {
+                let to = new_classes.get(class)?;
+                (from != to).then(|| MovedClass {
+                    class: class.clone(),
+                    from: from.clone(),
+                    to: to.clone(),
+                })
+            })
+            .collect();
+        Self {
+            teachers: Changes::between(
+                names(before.teachers().map(|t| &t.name)),
+                names(after.teachers().map(|t| &t.name)),
+            ),
+            grades: Changes::between(
+                names(before.grades().map(|g| &g.name)),
+                names(after.grades().map(|g| &g.name)),
+            ),
+            subjects: Changes::between(
+                names(before.subjects().map(|s| &s.name)),
+                names(after.subjects().map(|s| &s.name)),
+            ),
+            labs: Changes::between(
+                names(before.labs().map(|l| &l.name)),
+                names(after.labs().map(|l| &l.name)),
+            ),
+            classes: Changes::between(
+                old_classes.into_keys().collect(),
+                new_classes.into_keys().collect(),
+            ),
+            moved,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.teachers.is_empty()
+            && self.grades.is_empty()
+            && self.subjects.is_empty()
+            && self.labs.is_empty()
+            && self.classes.is_empty()
+            && self.moved.is_empty()
+    }
+
+    /// The classes in the rules that refer to something removed or moved. Wildcards and groups
+    /// are expanded against the school from `before`, and names are compared after replacing
+    /// aliases.
+    pub fn affected_rules(&self, rules: &Rules, before: &School) -> Vec<AffectedRule> {
+        let aliases = &rules.aliases;
+        let mut affected = vec![];
+        for class in rules.expand(before) {
+            let name = SavedClass {
+                teacher: aliases.teacher(class.teacher),
+                grade: aliases.grade(class.grade),
+                subject: aliases.subject(class.subject),
+            };
+            let mut reasons = vec![];
+            if self.teachers.removed.contains(&name.teacher) {
+                reasons.push(Reason::TeacherRemoved);
+            }
+            if self.grades.removed.contains(&name.grade) {
+                reasons.push(Reason::GradeRemoved);
+            }
+            if self.subjects.removed.contains(&name.subject) {
+                reasons.push(Reason::SubjectRemoved);
+            }
+            if reasons.is_empty() && self.classes.removed.contains(&name) {
+                reasons.push(Reason::ClassRemoved);
+            }
+            if self.moved.iter().any(|m| m.class == name) {
+                reasons.push(Reason::ClassMoved);
+            }
+            for lab in class.labs.iter().map(|lab| aliases.lab(lab)) {
+                if self.labs.removed.contains(&lab) {
+                    reasons.push(Reason::LabRemoved(lab));
+                }
+            }
+            affected.extend(reasons.into_iter().map(|reason| AffectedRule {
+                class: name.clone(),
+                reason,
+            }));
+        }
+        affected
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::{school::Day::Monday, testing};
+
+    #[test]
+    fn flags_rules_behind_globs_groups_and_aliases() {
+        let before = testing::school(
+            &[
+                ("1A", "Química", "Ana", Monday, "7:30"),
+                ("1B", "Química", "Ana", Monday, "8:20"),
+                ("2A", "Química", "Ana", Monday, "9:10"),
+            ],
+            &["L1", "L2"],
+        );
+        let after = testing::school(&[("2A", "Química", "Ana", Monday, "9:10")], &["L1"]);
+        let rules = testing::rules(json!({
+            "classes": [{"subject": "Química", "teachers": [
+                {"name": "*", "grades": [{"name": "1*", "labs": ["@todos"]}]},
+                {"name": "Profa. Ana", "grades": [{"name": "2A", "labs": ["L1"]}]},
+            ]}],
+            "groups": {"labs": {"todos": ["L1", "L2"]}},
+            "aliases": {"teachers": {"Profa. Ana": "Ana"}},
+        }));
+        let diff = SchoolDiff::new(&before, &after);
+        let affected = diff.affected_rules(&rules, &before);
+        let reasons = |grade: &str| -> Vec<Reason> {
+            (affected.iter())
+                .filter(|a| a.class.grade == grade && a.class.teacher == "Ana")
+                .map(|a| a.reason.clone())
+                .collect()
+        };
+        let l2 = Reason::LabRemoved("L2".into());
+        assert_eq!(reasons("1A"), [Reason::GradeRemoved, l2.clone()]);
+        assert_eq!(reasons("1B"), [Reason::GradeRemoved, l2]);
+        assert_eq!(reasons("2A"), []);
+    }
+}
@@ -1,3 +1,5 @@
+use std::ops::ControlFlow;
+
 /// Used to solve a backtracking problem
 pub trait State<Context>: Sized {
     /// Get all the successive states from the current state
@@ -7,25 +9,81 @@ pub trait State<Context>: Sized {
     fn is_goal(&self, ctx: &Context) -> bool;
 }
 
+/// Gets notified of what the search is doing
+pub trait Monitor {
+    /// Called every time a state is visited. Breaking aborts the search.
+    fn visit(&mut self) -> ControlFlow<()> {
+        ControlFlow::Continue(())
+    }
+
+    /// Called every time a state is pruned without leading to the goal
+    fn backtrack(&mut self) {}
+}
+
+impl Monitor for () {}
+
+pub enum Outcome<St> {
+    /// The goal state was found
+    Found(St),
+    /// Every state was searched and none is the goal
+    Exhausted,
+    /// The monitor aborted the search while visiting this state
+    Aborted(St),
+}
+
 /// Solve the backtracking problem using the specified state (recursive)
-pub fn solve<St, Ct>(ctx: &Ct, state: St) -> Option<St>
+pub fn solve<St, Ct>(ctx: &Ct, state: St, monitor: &mut impl Monitor) -> Outcome<St>
 where
     St: State<Ct>,
 {
+    if monitor.visit().is_break() {
+        return Outcome::Aborted(state);
+    }
+
     // Return once we found the goal
     if state.is_goal(ctx) {
-        return Some(state);
+        return Outcome::Found(state);
     }
 
     // Search the successors for the goal
     for child in state.successors(ctx) {
         // Check if the state leads to a solution
-        match solve(ctx, child) {
-            Some(solution) => return Some(solution),
-            None => continue,
+        match solve(ctx, child, monitor) {
+            Outcome::Exhausted => continue,
+            outcome => return outcome,
         }
     }
 
     // No solution found, prune this tree
-    None
+    monitor.backtrack();
+    Outcome::Exhausted
+}
+
+/// Search every goal state, handing each one to `found` until it breaks (recursive).
+///
+/// Breaks with the state being visited if the monitor aborted the search, or with `None` if
+/// `found` stopped it.
+pub fn search<St, Ct>(
+    ctx: &Ct,
+    state: St,
+    monitor: &mut impl Monitor,
+    found: &mut impl FnMut(St) -> ControlFlow<()>,
+) -> ControlFlow<Option<St>>
+where
+    St: State<Ct>,
+{
+    if monitor.visit().is_break() {
+        return ControlFlow::Break(Some(state));
+    }
+
+    if state.is_goal(ctx) {
+        return found(state).map_break(|_| None);
+    }
+
+    for child in state.successors(ctx) {
+        search(ctx, child, monitor, found)?;
+    }
+
+    monitor.backtrack();
+    ControlFlow::Continue(())
 }
@@ -0,0 +1,138 @@
+use std::collections::BTreeMap;
+
+use super::{ClassId, Decided, LabSlotId};
+use crate::{
+    rules::{Aliases, LabCapacity},
+    school::{Grade, Laboratory, School},
+};
+
+/// How many classes and students each lab takes at once
+#[derive(Clone, Default)]
+pub(super) struct Capacity {
+    /// Classes each lab takes at once, when more than one
+    concurrent: BTreeMap<usize, usize>,
+    /// Students each lab seats, when known
+    seats: BTreeMap<usize, usize>,
+    /// Students in the group of each class, when the size of its grade is known. A split class
+    /// has half of them in each lab.
+    sizes: BTreeMap<ClassId, usize>,
+}
+
+impl Capacity {
+    pub fn new(
+        school: &School,
+        labs: &BTreeMap<String, LabCapacity>,
+        sizes: &BTreeMap<String, usize>,
+        aliases: &Aliases,
+        split: &BTreeMap<usize, Option<usize>>,
+    ) -> Self {
+        let mut capacity = Self::default();
+        for (name, lab) in labs {
+            let Some(lab_id) = school.labs.find_key(&Laboratory {
+                name: aliases.lab(name),
+            }) else {
+                continue;
+            };
+            if lab.classes > 1 {
+                capacity.concurrent.insert(lab_id, lab.classes);
+            }
+            if let Some(seats) = lab.seats {
+                capacity.seats.insert(lab_id, seats);
+            }
+        }
+        let grades: BTreeMap<usize, usize> = (sizes.iter())
+            .filter_map(|(name, &size)| {
+                let grade_id = school.grades.find_key(&Grade {
+                    name: aliases.grade(name),
+                })?;
+                Some((grade_id, size))
+            })
+            .collect();
+        for (&class_id, class) in school.classes.iter() {
+            if let Some(&size) = grades.get(&class.grade) {
+                let size = if split.contains_key(&class_id) {
+                    size.div_ceil(2)
+                } else {
+                    size
+                };
+                capacity.sizes.insert(ClassId(class_id), size);
+            }
+        }
+        capacity
+    }
+
+    /// How many classes the lab takes at once
+    pub fn concurrent(&self, lab: usize) -> usize {
+        self.concurrent.get(&lab).copied().unwrap_or(1)
+    }
+
+    pub fn seats(&self, lab: usize) -> Option<usize> {
+        self.seats.get(&lab).copied()
+    }
+
+    /// Students in the classes, counting the ones of unknown size as empty
+    pub fn students(&self, classes: &[ClassId]) -> usize {
+        classes.iter().filter_map(|c| self.sizes.get(c)).sum()
+    }
+
+    /// Whether the lab takes all of the classes at once
+    pub fn fits(&self, lab: usize, classes: &[ClassId]) -> bool {
+        classes.len() <= self.concurrent(lab)
+            && self
+                .seats(lab)
+                .is_none_or(|seats| self.students(classes) <= seats)
+    }
+
+    /// Whether the class can join the ones already in every lab slot of the placement
+    pub fn has_room(&self, occupied: &Decided, class: ClassId, placement: &[LabSlotId]) -> bool {
+        placement.iter().all(|slot| {
+            let mut classes: Vec<ClassId> = (occupied.get(slot).into_iter().flatten())
+                .filter(|&&other| other != class)
+                .copied()
+                .collect();
+            classes.push(class);
+            self.fits(slot.lab, &classes)
+        })
+    }
+
+    /// Sets of options of different classes that would take more seats than the lab has,
+    /// leaving out the ones that contain a smaller such set
+    pub fn overfull<T: Copy>(&self, lab: usize, options: &[(T, ClassId)]) -> Vec<Vec<T>> {
+        let Some(seats) = self.seats(lab) else {
+            return vec![];
+        };
+        let mut overfull = vec![];
+        let mut chosen = vec![];
+        self.grow(
+            seats,
+            self.concurrent(lab),
+            options,
+            &mut chosen,
+            &mut overfull,
+        );
+        overfull
+    }
+
+    fn grow<T: Copy>(
+        &self,
+        seats: usize,
+        concurrent: usize,
+        options: &[(T, ClassId)],
+        chosen: &mut Vec<(T, ClassId)>,
+        overfull: &mut Vec<Vec<T>>,
+    ) {
+        for (i, &(option, class)) in options.iter().enumerate() {
+            if chosen.iter().any(|(_, c)| *c == class) {
+                continue;
+            }
+            chosen.push((option, class));
+            let classes: Vec<ClassId> = chosen.iter().map(|(_, c)| *c).collect();
+            if self.students(&classes) > seats {
+                overfull.push(chosen.iter().map(|(o, _)| *o).collect());
+            } else if chosen.len() < concurrent {
+                self.grow(seats, concurrent, &options[i + 1..], chosen, overfull);
+            }
+            chosen.pop();
+        }
+    }
+}
@@ -0,0 +1,265 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    iter,
+};
+
+use super::{
+    candidates, solution::Cap, ClassId, Decided, LabSlotId, LabSlottedClass, Placement, Problem,
+};
+use crate::{
+    rules::{Aliases, Caps, ANY},
+    school::{Day, Laboratory, School, Slot},
+    solver::solution::Warning,
+};
+
+/// How many sessions each cap allows, with what is needed to tell which caps a placement counts
+/// towards
+#[derive(Clone, Default)]
+pub(super) struct Limits {
+    max: BTreeMap<Cap, usize>,
+    /// The teachers and grade of each class, with two teachers when split between them
+    classes: BTreeMap<ClassId, (Vec<usize>, usize)>,
+    days: BTreeMap<usize, Day>,
+}
+
+/// The limit for a name, or the one for every name
+fn limit(
+    limits: &BTreeMap<String, usize>,
+    name: &str,
+    canonical: impl Fn(&str) -> String,
+) -> Option<usize> {
+    limits
+        .iter()
+        .find(|(n, _)| *n != ANY && canonical(n) == name)
+        .or_else(|| limits.get_key_value(ANY))
+        .map(|(_, &max)| max)
+}
+
+impl Limits {
+    pub fn new(
+        school: &School,
+        caps: &Caps,
+        aliases: &Aliases,
+        weeks: usize,
+        split: &BTreeMap<usize, Option<usize>>,
+    ) -> Self {
+        let mut max = BTreeMap::new();
+        if caps.is_empty() {
+            return Self::default();
+        }
+        for (&id, teacher) in school.teachers.iter() {
+            if let Some(n) = limit(&caps.teacher_per_week, &teacher.name, |t| {
+                aliases.teacher(t)
+            }) {
+                max.extend((0..weeks).map(|week| (Cap::TeacherPerWeek(id, week), n)));
+            }
+        }
+        let days: BTreeSet<Day> = school.slots().map(|s| s.day).collect();
+        let days: Vec<(usize, Day)> = (0..weeks)
+            .flat_map(|week| days.iter().map(move |&day| (week, day)))
+            .collect();
+        for (&id, grade) in school.grades.iter() {
+            if let Some(n) = limit(&caps.grade_per_day, &grade.name, |g| aliases.grade(g)) {
+                max.extend((days.iter()).map(|&(week, day)| (Cap::GradePerDay(id, week, day), n)));
+            }
+        }
+        for (&id, Laboratory { name }) in school.labs.iter() {
+            if let Some(n) = limit(&caps.lab_per_day, name, |l| aliases.lab(l)) {
+                max.extend((days.iter()).map(|&(week, day)| (Cap::LabPerDay(id, week, day), n)));
+            }
+        }
+        let classes = (school.classes.iter())
+            .map(|(&id, class)| {
+                let second = split.get(&id).copied().flatten();
+                let teachers = iter::once(class.teacher).chain(second).collect();
+                (ClassId(id), (teachers, class.grade))
+            })
+            .collect();
+        let days = (school.slots.iter())
+            .map(|(&id, slot): (&usize, &Slot)| (id, slot.day))
+            .collect();
+        Self { max, classes, days }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.max.is_empty()
+    }
+
+    /// The caps the sessions of the class count towards in each week it meets, limited or not
+    pub fn counted(&self, class: ClassId, placement: &[LabSlotId]) -> Vec<Cap> {
+        let (teachers, grade) = &self.classes[&class];
+        // Every period of a session is on the same day
+        let day = |week: usize| {
+            let slot = placement.iter().find(|s| s.week == week).unwrap().slot;
+            self.days[&slot]
+        };
+        let weeks: BTreeSet<usize> = placement.iter().map(|s| s.week).collect();
+        let labs: BTreeSet<(usize, usize)> = (placement.iter())
+            .map(|&LabSlotId { lab, week, .. }| (lab, week))
+            .collect();
+        let mut counted = vec![];
+        for week in weeks {
+            counted.extend(teachers.iter().map(|&t| Cap::TeacherPerWeek(t, week)));
+            counted.push(Cap::GradePerDay(*grade, week, day(week)));
+        }
+        for (lab, week) in labs {
+            counted.push(Cap::LabPerDay(lab, week, day(week)));
+        }
+        counted
+    }
+
+    /// The caps that are limited, with their limits
+    pub fn limited(&self, class: ClassId, placement: &[LabSlotId]) -> Vec<(Cap, usize)> {
+        if self.is_empty() {
+            return vec![];
+        }
+        (self.counted(class, placement).into_iter())
+            .filter_map(|cap| Some((cap, *self.max.get(&cap)?)))
+            .collect()
+    }
+
+    /// How many sessions count towards each cap
+    pub fn used<'p>(
+        &self,
+        placements: impl Iterator<Item = (ClassId, &'p Placement)>,
+    ) -> BTreeMap<Cap, usize> {
+        let mut used = BTreeMap::new();
+        for (class, placement) in placements {
+            for (cap, _) in self.limited(class, placement) {
+                *used.entry(cap).or_default() += 1;
+            }
+        }
+        used
+    }
+
+    /// A cap that would be exceeded by adding a session of the class
+    pub fn reached(
+        &self,
+        used: &BTreeMap<Cap, usize>,
+        class: ClassId,
+        placement: &[LabSlotId],
+    ) -> Option<(Cap, usize)> {
+        (self.limited(class, placement).into_iter())
+            .find(|(cap, max)| used.get(cap).copied().unwrap_or_default() >= *max)
+    }
+
+    /// Whether a session of the class fits among the decided ones
+    pub fn allows(&self, decided: &Decided, class: ClassId, placement: &[LabSlotId]) -> bool {
+        if self.is_empty() {
+            return true;
+        }
+        let placements = super::placements(decided);
+        let used = self.used(placements.iter().map(|(c, p)| (*c, p)));
+        self.reached(&used, class, placement).is_none()
+    }
+
+    /// Whether every cap stays within its limit after adding `changes` to the sessions it
+    /// counts
+    pub fn fits(&self, used: &BTreeMap<Cap, usize>, changes: &BTreeMap<Cap, i64>) -> bool {
+        changes.iter().all(|(cap, &change)| {
+            let used = used.get(cap).copied().unwrap_or_default() as i64;
+            change <= 0 || used + change <= self.max[cap] as i64
+        })
+    }
+
+    /// Every limited cap with the options that count towards it
+    pub fn groups<'p, T: Copy>(
+        &self,
+        options: impl Iterator<Item = (T, ClassId, &'p Placement)>,
+    ) -> Vec<(usize, Vec<T>)> {
+        let mut groups: BTreeMap<Cap, Vec<T>> = BTreeMap::new();
+        for (option, class, placement) in options {
+            for (cap, _) in self.limited(class, placement) {
+                groups.entry(cap).or_default().push(option);
+            }
+        }
+        (groups.into_iter())
+            .map(|(cap, options)| (self.max[&cap], options))
+            .collect()
+    }
+}
+
+/// Warn about every class that could have had a better placement, or been placed at all, if not
+/// for a cap that was reached
+pub(super) fn capped(
+    school: &School,
+    problem: &Problem,
+    slotted: &[LabSlottedClass],
+) -> Vec<Warning> {
+    let limits = &problem.limits;
+    if limits.is_empty() {
+        return vec![];
+    }
+    let relax = problem.classes.iter().map(|c| c.1.len()).max();
+    let (ctx, remaining) = candidates(school, problem, relax.unwrap_or_default());
+    let assignment = super::assignment(slotted);
+    let occupied = super::decided(assignment.iter().map(|(c, p)| (*c, p)));
+    let mut warnings = vec![];
+    for class in remaining {
+        let current = assignment.get(&class);
+        let cost = current.map(|p| problem.cost(school, class, p));
+        // What the cap counts without the class itself
+        let used = limits.used(
+            (assignment.iter())
+                .filter(|(other, _)| **other != class)
+                .map(|(c, p)| (*c, p)),
+        );
+        let blocked = ctx.slots_of[&class].iter().find_map(|placement| {
+            let better = cost.is_none_or(|cost| problem.cost(school, class, placement) < cost);
+            let free = ctx.capacity.has_room(&occupied, class, placement);
+            if better && free {
+                limits.reached(&used, class, placement)
+            } else {
+                None
+            }
+        });
+        if let Some((cap, limit)) = blocked {
+            warnings.push(Warning::CapLimited {
+                class: class.0,
+                cap,
+                limit,
+            });
+        }
+    }
+    warnings
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::{
+        school::Day::Monday,
+        solver::{solution::Warning, Backend, Solver},
+        testing,
+    };
+
+    #[test]
+    fn moves_a_class_out_of_a_capped_lab() {
+        let school = testing::school(
+            &[
+                ("1A", "Química", "Ana", Monday, "7:30"),
+                ("1B", "Química", "Bia", Monday, "8:20"),
+            ],
+            &["L1", "L2"],
+        );
+        let rules = testing::rules(json!({
+            "classes": [{"subject": "Química", "teachers": [
+                {"name": "Ana", "grades": [{"name": "1A", "labs": ["L1", "L2"]}]},
+                {"name": "Bia", "grades": [{"name": "1B", "labs": ["L1", "L2"]}]},
+            ]}],
+            "caps": {"lab_per_day": {"L1": 1}},
+        }));
+        let (l1, l2) = (testing::lab(&school, "L1"), testing::lab(&school, "L2"));
+        for backend in [Backend::Backtracking, Backend::Sat] {
+            let solution = Solver::new(&school, &rules).backend(backend).solve();
+            assert!(solution.errors.is_empty());
+            let in_lab = |lab| solution.slotted.iter().filter(|s| s.lab == lab).count();
+            assert_eq!((in_lab(l1), in_lab(l2)), (1, 1));
+            let capped = (solution.warnings.iter())
+                .filter(|w| matches!(w, Warning::CapLimited { limit: 1, .. }))
+                .count();
+            assert_eq!(capped, 1);
+        }
+    }
+}
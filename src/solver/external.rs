@@ -0,0 +1,491 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io::{BufRead, BufReader, Read, Write},
+    str::FromStr,
+};
+
+use anyhow::{anyhow, bail};
+
+use super::{
+    candidates, into_slotted, sat::Encoding, warnings, ClassId, Context, Decided, LabSlotId,
+    Placement, Solver,
+};
+use crate::school::{ClassRef, Laboratory, School, Slot};
+use crate::solver::solution::{week_name, Error, Solution, Statistics};
+
+/// A file format understood by external solvers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    MiniZinc,
+    /// CPLEX LP, also read by CBC, GLPK, HiGHS and Gurobi
+    Lp,
+    /// DIMACS WCNF, read by MaxSAT solvers
+    Dimacs,
+}
+
+impl FromStr for Format {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "minizinc" | "mzn" => Ok(Format::MiniZinc),
+            "lp" => Ok(Format::Lp),
+            "dimacs" | "wcnf" | "cnf" => Ok(Format::Dimacs),
+            _ => Err(format!("Unknown format: {s}")),
+        }
+    }
+}
+
+/// Every placement of each class, with all of its lab choices allowed. Option `i` is variable
+/// `x{i + 1}` in every format.
+struct Instance {
+    ctx: Context,
+    remaining: Vec<ClassId>,
+    options: Vec<(ClassId, Placement)>,
+    /// Position of the option's lab in the class's lab choices, 0 being the first choice, plus
+    /// one when the teacher would rather have the class at another time
+    ranks: Vec<usize>,
+}
+
+impl Instance {
+    fn new(solver: &Solver) -> Self {
+        let problem = solver.resolve();
+        let relax = problem.classes.iter().map(|c| c.1.len()).max();
+        let (ctx, remaining) = candidates(solver.school, &problem, relax.unwrap_or_default());
+        let mut options = vec![];
+        let mut ranks = vec![];
+        for class in &remaining {
+            for (placement, cost) in ctx.slots_of[class].iter().zip(&ctx.costs[class]) {
+                options.push((*class, placement.clone()));
+                ranks.push(*cost);
+            }
+        }
+        Self {
+            ctx,
+            remaining,
+            options,
+            ranks,
+        }
+    }
+
+    /// Lab slots that are candidates for some class, numbered from 1
+    fn lab_slots(&self) -> BTreeMap<LabSlotId, usize> {
+        let mut lab_slots = BTreeMap::new();
+        for slot in self.options.iter().flat_map(|(_, slots)| slots) {
+            let next = lab_slots.len() + 1;
+            lab_slots.entry(*slot).or_insert(next);
+        }
+        lab_slots
+    }
+
+    /// How many classes and students every lab slot takes, with the options that use it,
+    /// numbered from 1
+    fn lab_slot_capacity(&self) -> Vec<(LabSlotId, usize, Option<usize>, Vec<usize>)> {
+        let mut by_slot: BTreeMap<LabSlotId, Vec<usize>> = BTreeMap::new();
+        for (i, (_, slots)) in self.options.iter().enumerate() {
+            for slot in slots {
+                by_slot.entry(*slot).or_default().push(i + 1);
+            }
+        }
+        let capacity = &self.ctx.capacity;
+        (by_slot.into_iter())
+            .map(|(slot, options)| {
+                let lab = slot.lab;
+                (slot, capacity.concurrent(lab), capacity.seats(lab), options)
+            })
+            .collect()
+    }
+
+    /// Students that the option brings to each of its lab slots
+    fn students(&self, option: usize) -> usize {
+        self.ctx.capacity.students(&[self.options[option - 1].0])
+    }
+
+    /// The limit of every cap with the options that count towards it, numbered from 1
+    fn caps(&self) -> Vec<(usize, Vec<usize>)> {
+        let options = (self.options.iter().enumerate()).map(|(i, (class, p))| (i + 1, *class, p));
+        self.ctx.limits.groups(options)
+    }
+
+    /// Cost of leaving a class out, higher than any combination of lab choices
+    fn unplaced_cost(&self) -> usize {
+        self.ranks.iter().max().map(|r| r + 1).unwrap_or(1) * self.remaining.len() + 1
+    }
+
+    fn describe(&self, school: &School, i: usize) -> String {
+        let (ClassId(class), placement) = &self.options[i];
+        let class: ClassRef = school.get(*class);
+        let lab: &Laboratory = school.get(placement[0].lab);
+        let cycle = placement.iter().any(|s| s.week > 0);
+        let slots: Vec<_> = (placement.iter())
+            .map(|s| {
+                let slot = school.get::<&Slot>(s.slot);
+                if cycle {
+                    format!("{slot} of week {}", week_name(s.week))
+                } else {
+                    slot.to_string()
+                }
+            })
+            .collect();
+        format!(
+            "{class} in {lab} at {}, cost {}",
+            slots.join(" and "),
+            self.ranks[i]
+        )
+    }
+
+    fn write_minizinc(&self, school: &School, out: &mut impl Write) -> anyhow::Result<()> {
+        let lab_slots = self.lab_slots();
+        let classes: BTreeMap<ClassId, usize> = self
+            .remaining
+            .iter()
+            .enumerate()
+            .map(|(i, class)| (*class, i + 1))
+            .collect();
+        let join = |v: Vec<usize>| {
+            v.iter()
+                .map(|x| x.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        writeln!(out, "% Option i places a class in one or more lab slots:")?;
+        for i in 0..self.options.len() {
+            writeln!(out, "%   {}: {}", i + 1, self.describe(school, i))?;
+        }
+        writeln!(out, "int: n_options = {};", self.options.len())?;
+        writeln!(out, "int: n_classes = {};", classes.len())?;
+        writeln!(out, "int: n_lab_slots = {};", lab_slots.len())?;
+        writeln!(out, "int: unplaced_cost = {};", self.unplaced_cost())?;
+        let class_of = self.options.iter().map(|(c, _)| classes[c]).collect();
+        let slots_of: Vec<_> = (self.options.iter())
+            .map(|(_, slots)| {
+                let slots: Vec<_> = slots.iter().map(|s| lab_slots[s]).collect();
+                format!("{{{}}}", join(slots))
+            })
+            .collect();
+        writeln!(
+            out,
+            "array[1..n_options] of 1..n_classes: class = [{}];",
+            join(class_of)
+        )?;
+        writeln!(
+            out,
+            "array[1..n_options] of set of 1..n_lab_slots: lab_slots = [{}];",
+            slots_of.join(", ")
+        )?;
+        writeln!(
+            out,
+            "array[1..n_options] of int: rank = [{}];",
+            join(self.ranks.clone())
+        )?;
+        writeln!(out, "array[1..n_options] of var bool: x;")?;
+        writeln!(out, "array[1..n_classes] of var bool: placed;")?;
+        writeln!(
+            out,
+            "constraint forall(c in 1..n_classes)(sum(i in 1..n_options where class[i] = c)(bool2int(x[i])) = bool2int(placed[c]));"
+        )?;
+        let mut concurrent = vec![1; lab_slots.len()];
+        for (slot, classes, _, _) in self.lab_slot_capacity() {
+            concurrent[lab_slots[&slot] - 1] = classes;
+        }
+        writeln!(
+            out,
+            "array[1..n_lab_slots] of int: concurrent = [{}];",
+            join(concurrent)
+        )?;
+        writeln!(
+            out,
+            "constraint forall(s in 1..n_lab_slots)(sum(i in 1..n_options where s in lab_slots[i])(bool2int(x[i])) <= concurrent[s]);"
+        )?;
+        for (_, _, seats, options) in self.lab_slot_capacity() {
+            if let Some(seats) = seats {
+                let sum: Vec<_> = (options.iter())
+                    .map(|&i| format!("{} * bool2int(x[{i}])", self.students(i)))
+                    .collect();
+                writeln!(out, "constraint {} <= {seats};", sum.join(" + "))?;
+            }
+        }
+        for (max, options) in self.caps() {
+            let sum: Vec<_> = options
+                .iter()
+                .map(|i| format!("bool2int(x[{i}])"))
+                .collect();
+            writeln!(out, "constraint {} <= {max};", sum.join(" + "))?;
+        }
+        writeln!(
+            out,
+            "solve minimize sum(i in 1..n_options)(rank[i] * bool2int(x[i])) + unplaced_cost * sum(c in 1..n_classes)(1 - bool2int(placed[c]));"
+        )?;
+        writeln!(out, "output [\"x = \\(x);\\n\"];")?;
+        Ok(())
+    }
+
+    fn write_lp(&self, school: &School, out: &mut impl Write) -> anyhow::Result<()> {
+        let unplaced_cost = self.unplaced_cost() as i64;
+        writeln!(out, "\\ Option xi places a class in one or more lab slots:")?;
+        for i in 0..self.options.len() {
+            writeln!(out, "\\   x{}: {}", i + 1, self.describe(school, i))?;
+        }
+        writeln!(
+            out,
+            "\\ Leaving a class out costs {unplaced_cost}, which is subtracted from each option"
+        )?;
+        writeln!(out, "Minimize")?;
+        write!(out, " cost:")?;
+        for (i, rank) in self.ranks.iter().enumerate() {
+            let cost = *rank as i64 - unplaced_cost;
+            write!(
+                out,
+                " {} {} x{}",
+                if cost < 0 { "-" } else { "+" },
+                cost.abs(),
+                i + 1
+            )?;
+        }
+        writeln!(out)?;
+        writeln!(out, "Subject To")?;
+        let mut by_class: BTreeMap<ClassId, Vec<usize>> = BTreeMap::new();
+        for (i, (class, _)) in self.options.iter().enumerate() {
+            by_class.entry(*class).or_default().push(i + 1);
+        }
+        let sum = |vars: &[usize]| {
+            vars.iter()
+                .map(|i| format!("x{i}"))
+                .collect::<Vec<_>>()
+                .join(" + ")
+        };
+        for (i, vars) in by_class.values().enumerate() {
+            writeln!(out, " class{}: {} <= 1", i + 1, sum(vars))?;
+        }
+        let lab_slots = self.lab_slot_capacity();
+        for (i, (_, concurrent, _, vars)) in lab_slots.iter().enumerate() {
+            writeln!(out, " lab_slot{}: {} <= {concurrent}", i + 1, sum(vars))?;
+        }
+        for (i, (_, _, seats, vars)) in lab_slots.iter().enumerate() {
+            if let Some(seats) = seats {
+                let students: Vec<_> = (vars.iter())
+                    .map(|&v| format!("{} x{v}", self.students(v)))
+                    .collect();
+                writeln!(out, " seats{}: {} <= {seats}", i + 1, students.join(" + "))?;
+            }
+        }
+        for (i, (max, vars)) in self.caps().iter().enumerate() {
+            writeln!(out, " cap{}: {} <= {max}", i + 1, sum(vars))?;
+        }
+        writeln!(out, "Binary")?;
+        for i in 0..self.options.len() {
+            writeln!(out, " x{}", i + 1)?;
+        }
+        writeln!(out, "End")?;
+        Ok(())
+    }
+
+    /// Write the problem as weighted clauses: placing each class and the rank literals of the
+    /// encoding are soft, so that classes can be left out and lab choices given up at the same
+    /// costs as in the other formats, and everything else is hard
+    fn write_dimacs(&self, school: &School, out: &mut impl Write) -> anyhow::Result<()> {
+        let encoding = Encoding::new(&self.ctx, &self.remaining);
+        let unplaced_cost = self.unplaced_cost();
+        let top = unplaced_cost * encoding.placed.len() + encoding.ranks.len() + 1;
+        writeln!(out, "c Variable i places a class in one or more lab slots:")?;
+        for i in 0..self.options.len() {
+            writeln!(out, "c   {}: {}", i + 1, self.describe(school, i))?;
+        }
+        writeln!(out, "c Other variables are auxiliary")?;
+        writeln!(
+            out,
+            "c Leaving a class out costs {unplaced_cost}, each unit of rank costs 1"
+        )?;
+        writeln!(
+            out,
+            "p wcnf {} {} {top}",
+            encoding.cnf.vars,
+            encoding.cnf.clauses.len() + encoding.ranks.len()
+        )?;
+        for (i, clause) in encoding.cnf.clauses.iter().enumerate() {
+            let weight = if encoding.placed.binary_search(&i).is_ok() {
+                unplaced_cost
+            } else {
+                top
+            };
+            write!(out, "{weight} ")?;
+            for lit in clause {
+                write!(out, "{lit} ")?;
+            }
+            writeln!(out, "0")?;
+        }
+        for rank in &encoding.ranks {
+            writeln!(out, "1 {} 0", -rank)?;
+        }
+        Ok(())
+    }
+
+    /// Read which options were chosen from the output of an external solver
+    fn read_chosen(&self, format: Format, input: impl Read) -> anyhow::Result<Vec<usize>> {
+        let mut chosen = vec![];
+        for line in BufReader::new(input).lines() {
+            let line = line?;
+            let line = line.trim();
+            match format {
+                // x = [true, false, ...];
+                Format::MiniZinc => {
+                    let Some(values) = line.strip_prefix("x = [") else {
+                        continue;
+                    };
+                    let values = values.trim_end_matches(';').trim_end_matches(']');
+                    chosen = values
+                        .split(',')
+                        .enumerate()
+                        .filter(|(_, v)| v.trim() == "true")
+                        .map(|(i, _)| i)
+                        .collect();
+                }
+                // Any line with a variable name followed by its value
+                Format::Lp => {
+                    let tokens: Vec<_> = line.split_whitespace().collect();
+                    for pair in tokens.windows(2) {
+                        let Some(Ok(var)) = pair[0].strip_prefix('x').map(|v| v.parse::<usize>())
+                        else {
+                            continue;
+                        };
+                        if pair[1].parse::<f64>().is_ok_and(|v| v > 0.5) {
+                            chosen.push(var - 1);
+                        }
+                    }
+                }
+                // v 1 -2 3 ... 0, or v 101... with the value of every variable in order
+                Format::Dimacs => {
+                    if line.starts_with("s UNSAT") || line == "UNSAT" {
+                        bail!("The external solver found no solution");
+                    }
+                    // Comments, the status and the cost of each model found
+                    if line.starts_with(['c', 's', 'o']) {
+                        continue;
+                    }
+                    let values = line.trim_start_matches('v').trim();
+                    let bits = values.len() > 1 && values.chars().all(|c| c == '0' || c == '1');
+                    if bits {
+                        let ones = values.char_indices().filter(|(_, c)| *c == '1');
+                        let ones = ones.map(|(i, _)| i).filter(|&i| i < self.options.len());
+                        chosen.extend(ones);
+                        continue;
+                    }
+                    for lit in values.split_whitespace() {
+                        let lit: i64 = lit.parse().map_err(|_| anyhow!("Bad literal: {lit}"))?;
+                        if lit > 0 && (lit as usize) <= self.options.len() {
+                            chosen.push(lit as usize - 1);
+                        }
+                    }
+                }
+            }
+        }
+        if let Some(&i) = chosen.iter().find(|&&i| i >= self.options.len()) {
+            bail!("Unknown option: x{}", i + 1);
+        }
+        Ok(chosen)
+    }
+}
+
+impl Solver<'_> {
+    /// Write the problem to be solved by an external solver. Every lab choice is allowed, with
+    /// its position in the choices as its cost, plus one at times the teacher would rather
+    /// avoid.
+    pub fn export_problem(&self, format: Format, mut out: impl Write) -> anyhow::Result<()> {
+        let instance = Instance::new(self);
+        match format {
+            Format::MiniZinc => instance.write_minizinc(self.school, &mut out),
+            Format::Lp => instance.write_lp(self.school, &mut out),
+            Format::Dimacs => instance.write_dimacs(self.school, &mut out),
+        }
+    }
+
+    /// Read the output of an external solver for the problem written by
+    /// [`Solver::export_problem`] with the same school and rules
+    pub fn import_solution(&self, format: Format, input: impl Read) -> anyhow::Result<Solution> {
+        let instance = Instance::new(self);
+        let problem = self.resolve();
+        let mut decided = Decided::new();
+        let mut placed = BTreeSet::new();
+        for i in instance.read_chosen(format, input)? {
+            let (class, slots) = &instance.options[i];
+            for slot in slots {
+                let classes = decided.entry(*slot).or_default();
+                classes.push(*class);
+                if !problem.capacity.fits(slot.lab, classes) {
+                    let lab: &Laboratory = self.school.get(slot.lab);
+                    let slot: &Slot = self.school.get(slot.slot);
+                    bail!("More classes than {lab} takes at {slot}");
+                }
+            }
+            if !placed.insert(*class) {
+                let class: ClassRef = self.school.get(class.0);
+                bail!("The class {class} was placed more than once");
+            }
+        }
+        let slotted = into_slotted(decided);
+        let errors = problem
+            .classes
+            .iter()
+            .filter(|(class, _)| !slotted.iter().any(|s| s.class == *class))
+            .map(|(class, labs)| {
+                if labs.is_empty() {
+                    Error::NoLabs(*class)
+                } else {
+                    Error::Missing(*class)
+                }
+            })
+            .collect();
+        Ok(Solution {
+            warnings: warnings(self.school, &problem, &slotted),
+            slotted,
+            errors,
+            statistics: Statistics::default(),
+            cancelled: false,
+            moved: vec![],
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::{school::Day::Monday, testing};
+
+    #[test]
+    fn imports_maxsat_solver_output() {
+        let school = testing::school(&[("1A", "Química", "Ana", Monday, "7:30")], &["L1", "L2"]);
+        let rules = testing::rules(json!({
+            "classes": [{"subject": "Química", "teachers": [
+                {"name": "Ana", "grades": [{"name": "1A", "labs": ["L1", "L2"]}]},
+            ]}],
+        }));
+        let solver = Solver::new(&school, &rules);
+        let mut problem = vec![];
+        solver.export_problem(Format::Dimacs, &mut problem).unwrap();
+        let problem = String::from_utf8(problem).unwrap();
+        let header = problem.lines().find(|l| l.starts_with("p wcnf")).unwrap();
+        let vars: usize = header.split_whitespace().nth(2).unwrap().parse().unwrap();
+        // Placing 1A in L2, its second choice, as RC2 and the newer bit string format report it
+        let lits: Vec<_> = (1..=vars as i64)
+            .map(|var| if var == 2 { var } else { -var }.to_string())
+            .collect();
+        let bits: String = (1..=vars)
+            .map(|var| if var == 2 { '1' } else { '0' })
+            .collect();
+        let transcripts = [
+            format!("c RC2\no 1\ns OPTIMUM FOUND\nv {} 0\n", lits.join(" ")),
+            format!("c EvalMaxSAT\no 1\ns OPTIMUM FOUND\nv {bits}\n"),
+        ];
+        let class = testing::class(&school, "1A", "Química", "Ana");
+        let l2 = testing::lab(&school, "L2");
+        for transcript in transcripts {
+            let solution = solver
+                .import_solution(Format::Dimacs, transcript.as_bytes())
+                .unwrap();
+            assert!(solution.errors.is_empty());
+            let labs: Vec<_> = solution.slotted.iter().map(|s| (s.class, s.lab)).collect();
+            assert_eq!(labs, [(class, l2)]);
+        }
+    }
+}
@@ -0,0 +1,541 @@
+use std::{
+    collections::BTreeMap,
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use super::{
+    assignment, candidates, caps::Limits, decided, into_slotted, warnings, ClassId, Context,
+    Decided, Placement, Problem, Tracker, PROGRESS_INTERVAL,
+};
+use crate::{
+    school::{Class, School},
+    solver::solution::{Cap, Error, Solution},
+};
+
+/// How often the clock and the cancel token are checked
+const CHECK_INTERVAL: u64 = 256;
+
+/// Starting temperature, in units of lab choice ranks
+const INITIAL_TEMPERATURE: f64 = 2.0;
+
+/// Cost of a party giving up the first lab choice of all of its classes, with
+/// [`Objective::Fair`]
+const UNFAIRNESS: f64 = 100.0;
+
+/// What an improvement phase is allowed to do
+#[derive(Debug, Clone, Copy)]
+pub struct Improvement {
+    /// How long to keep trying to improve
+    pub time: Duration,
+    /// Seed of the random moves, so that a run can be repeated
+    pub seed: u64,
+    /// What to improve
+    pub objective: Objective,
+}
+
+/// What makes a schedule better than another
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Objective {
+    /// Give up as few lab choices as possible overall
+    #[default]
+    Total,
+    /// Also spread the first lab choices given up as evenly as possible across teachers and
+    /// grades, so that the same ones don't lose them every time
+    Fair,
+}
+
+impl FromStr for Objective {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "total" => Ok(Objective::Total),
+            "fair" => Ok(Objective::Fair),
+            _ => Err(format!("Unknown objective: {s}")),
+        }
+    }
+}
+
+/// Who gives up lab choices, for [`Objective::Fair`]
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Party {
+    Teacher(usize),
+    Grade(usize),
+}
+
+/// The cost of each possible placement of a class, the lower the better
+struct Costs {
+    /// Cost of each candidate placement of a class
+    ranks: BTreeMap<(ClassId, Placement), usize>,
+    previous: BTreeMap<ClassId, Placement>,
+    /// Cost of moving a class from where it was in the previous solution
+    changed: usize,
+    /// Cost of leaving a class out
+    unplaced: usize,
+    /// The first lab choice of each class, or the first two when it's split
+    first: BTreeMap<ClassId, Vec<usize>>,
+    /// The teacher and grade of each class, only when the objective is fair
+    parties: BTreeMap<ClassId, [Party; 2]>,
+    /// How many classes each party has
+    sizes: BTreeMap<Party, usize>,
+}
+
+impl Costs {
+    fn new(school: &School, problem: &Problem, ctx: &Context, objective: Objective) -> Self {
+        let mut ranks = BTreeMap::new();
+        for (&class, placements) in &ctx.slots_of {
+            for placement in placements {
+                let cost = problem.cost(school, class, placement);
+                ranks.insert((class, placement.clone()), cost);
+            }
+        }
+        let worst = ranks.values().max();
+        // A change outweighs any placement and leaving a class out outweighs any changes
+        let changed = worst.unwrap_or(&0) + 1;
+        let unplaced = changed * 2 * (problem.classes.len() + 1);
+        let first = (problem.classes.iter())
+            .map(|(class, labs)| {
+                let first = &labs[..problem.labs_at_once(*class).min(labs.len())];
+                (ClassId(*class), first.to_vec())
+            })
+            .collect();
+        let parties = match objective {
+            Objective::Total => BTreeMap::new(),
+            Objective::Fair => (ctx.slots_of.keys())
+                .map(|&class| {
+                    let Class { teacher, grade, .. } = school.classes.get(class.0).unwrap();
+                    (class, [Party::Teacher(*teacher), Party::Grade(*grade)])
+                })
+                .collect(),
+        };
+        let mut sizes = BTreeMap::new();
+        for party in parties.values().flatten() {
+            *sizes.entry(*party).or_default() += 1;
+        }
+        Self {
+            ranks,
+            previous: problem.previous.clone(),
+            changed,
+            unplaced,
+            first,
+            parties,
+            sizes,
+        }
+    }
+
+    /// Cost of the class at `placement`. A placement that is not a candidate, like one from a
+    /// previous solution the rules no longer allow, costs as much as leaving the class out.
+    fn of(&self, class: ClassId, placement: Option<&Placement>) -> usize {
+        let Some(placement) = placement else {
+            return self.unplaced;
+        };
+        let Some(rank) = self.ranks.get(&(class, placement.clone())) else {
+            return self.unplaced;
+        };
+        let changed = self.previous.get(&class).is_some_and(|p| p != placement);
+        rank + if changed { self.changed } else { 0 }
+    }
+
+    /// The first lab choices given up by each party of the class when it is at `placement`
+    fn given_up(&self, class: ClassId, placement: Option<&Placement>) -> Vec<(Party, i64)> {
+        let Some(parties) = self.parties.get(&class) else {
+            return vec![];
+        };
+        let given_up = placement.is_none_or(|p| {
+            (p.iter()).any(|s| !self.first.get(&class).is_some_and(|f| f.contains(&s.lab)))
+        });
+        parties.iter().map(|p| (*p, given_up as i64)).collect()
+    }
+
+    /// Cost of a party giving up `given_up` first lab choices. It grows with the square of the
+    /// share given up, so that the parties that gave up the most are the first to win one back.
+    fn unfairness(&self, party: Party, given_up: usize) -> f64 {
+        let share = given_up as f64 / self.sizes[&party] as f64;
+        UNFAIRNESS * share * share
+    }
+
+    /// How much worse giving up `changes` more first lab choices makes the parties
+    fn unfairness_delta(
+        &self,
+        given_up: &BTreeMap<Party, usize>,
+        changes: &BTreeMap<Party, i64>,
+    ) -> f64 {
+        (changes.iter())
+            .map(|(&party, change)| {
+                let before = given_up.get(&party).copied().unwrap_or_default();
+                let after = (before as i64 + change) as usize;
+                self.unfairness(party, after) - self.unfairness(party, before)
+            })
+            .sum()
+    }
+}
+
+/// Add changes to what each key counts
+fn add<K: Ord>(counts: &mut BTreeMap<K, usize>, changes: BTreeMap<K, i64>) {
+    for (key, change) in changes {
+        let count = counts.entry(key).or_default();
+        *count = (*count as i64 + change) as usize;
+    }
+}
+
+/// Where every class is, kept in both directions
+#[derive(Clone)]
+struct Schedule {
+    placement: BTreeMap<ClassId, Placement>,
+    occupied: Decided,
+    cost: f64,
+    /// Sessions counted by each cap
+    used: BTreeMap<Cap, usize>,
+    /// First lab choices given up by each party
+    given_up: BTreeMap<Party, usize>,
+}
+
+impl Schedule {
+    fn place(&mut self, class: ClassId, placement: Placement) {
+        if let Some(old) = self.placement.remove(&class) {
+            for slot in old {
+                let classes = self.occupied.entry(slot).or_default();
+                classes.retain(|&c| c != class);
+                if classes.is_empty() {
+                    self.occupied.remove(&slot);
+                }
+            }
+        }
+        for &slot in &placement {
+            self.occupied.entry(slot).or_default().push(class);
+        }
+        self.placement.insert(class, placement);
+    }
+}
+
+/// A change to the schedule that keeps every class in one of its candidate placements
+enum Step {
+    Move(ClassId, Placement),
+    Swap(ClassId, ClassId),
+}
+
+fn random_step(
+    ctx: &Context,
+    classes: &[ClassId],
+    schedule: &Schedule,
+    rng: &mut StdRng,
+) -> Option<Step> {
+    let class = classes[rng.gen_range(0..classes.len())];
+    let placements = &ctx.slots_of[&class];
+    let placement = &placements[rng.gen_range(0..placements.len())];
+    let current = schedule.placement.get(&class);
+    if current == Some(placement) {
+        return None;
+    }
+    if ctx.capacity.has_room(&schedule.occupied, class, placement) {
+        return Some(Step::Move(class, placement.clone()));
+    }
+    let mut others: Vec<ClassId> = (placement.iter())
+        .flat_map(|slot| schedule.occupied.get(slot).into_iter().flatten().copied())
+        .filter(|&other| other != class)
+        .collect();
+    others.sort();
+    others.dedup();
+    match (&others[..], current) {
+        // Only swap classes that take exactly each other's lab slots
+        (&[other], Some(current))
+            if schedule.placement[&other] == *placement
+                && ctx.slots_of[&other].contains(current)
+                && (current.iter()).all(|s| ctx.capacity.fits(s.lab, &[other]))
+                && (placement.iter()).all(|s| ctx.capacity.fits(s.lab, &[class])) =>
+        {
+            Some(Step::Swap(class, other))
+        }
+        _ => None,
+    }
+}
+
+fn delta(costs: &Costs, schedule: &Schedule, step: &Step) -> f64 {
+    let cost = |class: ClassId, placement: Option<&Placement>| costs.of(class, placement) as f64;
+    match step {
+        Step::Move(class, placement) => {
+            cost(*class, Some(placement)) - cost(*class, schedule.placement.get(class))
+        }
+        Step::Swap(a, b) => {
+            let (placement_a, placement_b) = (&schedule.placement[a], &schedule.placement[b]);
+            cost(*a, Some(placement_b)) + cost(*b, Some(placement_a))
+                - cost(*a, Some(placement_a))
+                - cost(*b, Some(placement_b))
+        }
+    }
+}
+
+/// How a step changes what `counted` counts for the classes it moves
+fn tally<K: Ord>(
+    schedule: &Schedule,
+    step: &Step,
+    counted: impl Fn(ClassId, Option<&Placement>) -> Vec<(K, i64)>,
+) -> BTreeMap<K, i64> {
+    let mut changes = BTreeMap::new();
+    let mut count = |class: ClassId, placement: Option<&Placement>, by: i64| {
+        for (key, n) in counted(class, placement) {
+            *changes.entry(key).or_default() += n * by;
+        }
+    };
+    match step {
+        Step::Move(class, placement) => {
+            count(*class, schedule.placement.get(class), -1);
+            count(*class, Some(placement), 1);
+        }
+        Step::Swap(a, b) => {
+            let (placement_a, placement_b) = (&schedule.placement[a], &schedule.placement[b]);
+            count(*a, Some(placement_a), -1);
+            count(*b, Some(placement_b), -1);
+            count(*a, Some(placement_b), 1);
+            count(*b, Some(placement_a), 1);
+        }
+    }
+    changes
+}
+
+/// How a step changes the sessions counted by each cap
+fn cap_changes(limits: &Limits, schedule: &Schedule, step: &Step) -> BTreeMap<Cap, i64> {
+    tally(schedule, step, |class, placement| {
+        (placement
+            .map(|p| limits.limited(class, p))
+            .unwrap_or_default())
+        .into_iter()
+        .map(|(cap, _)| (cap, 1))
+        .collect()
+    })
+}
+
+fn apply(schedule: &mut Schedule, step: Step, delta: f64) {
+    match step {
+        Step::Move(class, placement) => schedule.place(class, placement),
+        Step::Swap(a, b) => {
+            let placement_a = schedule.placement[&a].clone();
+            let placement_b = schedule.placement[&b].clone();
+            schedule.place(a, placement_b);
+            schedule.place(b, placement_a);
+        }
+    }
+    schedule.cost += delta;
+}
+
+/// Move and swap classes between their candidate lab slots with simulated annealing, starting
+/// from `placement` and returning the best placement found. Every lab choice of a class is a
+/// candidate, and classes left out are put back in when there is room.
+pub(super) fn anneal(
+    school: &School,
+    problem: &Problem,
+    placement: BTreeMap<ClassId, Placement>,
+    improvement: Improvement,
+    tracker: &mut Tracker,
+) -> BTreeMap<ClassId, Placement> {
+    let relax = problem.classes.iter().map(|c| c.1.len()).max();
+    let (ctx, classes) = candidates(school, problem, relax.unwrap_or_default());
+    if classes.is_empty() {
+        return placement;
+    }
+    let costs = Costs::new(school, problem, &ctx, improvement.objective);
+    let occupied = decided(placement.iter().map(|(c, p)| (*c, p)));
+    let mut given_up = BTreeMap::new();
+    for class in &classes {
+        let shares = costs.given_up(*class, placement.get(class));
+        add(&mut given_up, shares.into_iter().collect());
+    }
+    let cost = classes
+        .iter()
+        .map(|class| costs.of(*class, placement.get(class)) as f64)
+        .sum::<f64>()
+        + (given_up.iter())
+            .map(|(party, n)| costs.unfairness(*party, *n))
+            .sum::<f64>();
+    let limits = &problem.limits;
+    let used = limits.used(placement.iter().map(|(c, p)| (*c, p)));
+    let mut schedule = Schedule {
+        placement,
+        occupied,
+        cost,
+        used,
+        given_up,
+    };
+    let mut best = schedule.clone();
+    let mut rng = StdRng::seed_from_u64(improvement.seed);
+    let started = Instant::now();
+    let mut temperature = INITIAL_TEMPERATURE;
+    for iteration in 1.. {
+        if iteration % CHECK_INTERVAL == 0 {
+            if iteration % PROGRESS_INTERVAL == 0 {
+                tracker.report();
+            }
+            let elapsed = started.elapsed();
+            if elapsed >= improvement.time || tracker.is_cancelled() {
+                break;
+            }
+            let left = 1.0 - elapsed.as_secs_f64() / improvement.time.as_secs_f64();
+            temperature = INITIAL_TEMPERATURE * left;
+        }
+        let Some(step) = random_step(&ctx, &classes, &schedule, &mut rng) else {
+            continue;
+        };
+        let changes = cap_changes(limits, &schedule, &step);
+        if !limits.fits(&schedule.used, &changes) {
+            continue;
+        }
+        let shares = tally(&schedule, &step, |class, p| costs.given_up(class, p));
+        let delta =
+            delta(&costs, &schedule, &step) + costs.unfairness_delta(&schedule.given_up, &shares);
+        let accept =
+            delta <= 0.0 || temperature > 0.0 && rng.gen::<f64>() < (-delta / temperature).exp();
+        if accept {
+            apply(&mut schedule, step, delta);
+            add(&mut schedule.used, changes);
+            add(&mut schedule.given_up, shares);
+            if schedule.cost < best.cost {
+                best = schedule.clone();
+                tracker.statistics.improvements += 1;
+            }
+        }
+    }
+    best.placement
+}
+
+/// Run an improvement phase on a solution found for `problem`
+pub(super) fn improve(
+    school: &School,
+    problem: &Problem,
+    solution: Solution,
+    improvement: Improvement,
+    tracker: &mut Tracker,
+) -> Solution {
+    tracker.statistics = solution.statistics;
+    let placement = anneal(
+        school,
+        problem,
+        assignment(&solution.slotted),
+        improvement,
+        tracker,
+    );
+    let slotted = into_slotted(decided(placement.iter().map(|(c, p)| (*c, p))));
+    let errors = solution
+        .errors
+        .into_iter()
+        .filter(|e| match e {
+            Error::Missing(class) => !slotted.iter().any(|s| s.class == *class),
+            Error::NoLabs(_) => true,
+        })
+        .collect();
+    tracker.report();
+    Solution {
+        warnings: warnings(school, problem, &slotted),
+        slotted,
+        errors,
+        statistics: std::mem::take(&mut tracker.statistics),
+        ..solution
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::{
+        school::Day::Monday,
+        solver::{verify::verify, Solver},
+        testing,
+    };
+
+    #[test]
+    fn fair_spreads_the_lost_choices() {
+        let school = testing::school(
+            &[
+                ("1A", "Química", "Ana", Monday, "7:30"),
+                ("1C", "Química", "Bia", Monday, "7:30"),
+                ("1B", "Química", "Ana", Monday, "8:20"),
+                ("1D", "Química", "Bia", Monday, "8:20"),
+            ],
+            &["L1", "L2", "L9"],
+        );
+        // Bia's second choice can never be used, so she loses more than Ana with each class
+        // out of L1 and a plain solve takes L1 from Ana both times
+        let rules = testing::rules(json!({
+            "classes": [{"subject": "Química", "teachers": [
+                {"name": "Ana", "grades": [
+                    {"name": "1A", "labs": ["L1", "L2"]},
+                    {"name": "1B", "labs": ["L1", "L2"]},
+                ]},
+                {"name": "Bia", "grades": [
+                    {"name": "1C", "labs": ["L1", "L9", "L2"]},
+                    {"name": "1D", "labs": ["L1", "L9", "L2"]},
+                ]},
+            ]}],
+            "forbidden_times": {"L9": ["07:30", "08:20"]},
+        }));
+        let l1 = testing::lab(&school, "L1");
+        let most_lost = |objective| {
+            let improvement = Improvement {
+                time: Duration::from_millis(200),
+                seed: 0,
+                objective,
+            };
+            let solution = Solver::new(&school, &rules).improve(improvement).solve();
+            assert!(solution.errors.is_empty());
+            let mut lost: BTreeMap<usize, usize> = BTreeMap::new();
+            for s in solution.slotted.iter().filter(|s| s.lab != l1) {
+                *lost
+                    .entry(school.classes.get(s.class).unwrap().teacher)
+                    .or_default() += 1;
+            }
+            lost.into_values().max().unwrap_or_default()
+        };
+        assert_eq!(most_lost(Objective::Total), 2);
+        assert_eq!(most_lost(Objective::Fair), 1);
+    }
+
+    #[test]
+    fn never_makes_the_schedule_worse_or_breaks_the_rules() {
+        let school = testing::school(
+            &[
+                ("1A", "Química", "Ana", Monday, "7:30"),
+                ("1B", "Química", "Ana", Monday, "8:20"),
+                ("1B", "Física", "Bruno", Monday, "7:30"),
+                ("1C", "Física", "Carla", Monday, "7:30"),
+                ("1C", "Química", "Ana", Monday, "9:10"),
+            ],
+            &["L1", "L2", "L3"],
+        );
+        let rules = testing::rules(json!({
+            "classes": [
+                {"subject": "Química", "teachers": [
+                    {"name": "Ana", "grades": [
+                        {"name": "1A", "labs": ["L1", "L2"]},
+                        {"name": "1B", "labs": ["L3", "L1"]},
+                        {"name": "1C", "labs": ["L2"]},
+                    ]},
+                ]},
+                {"subject": "Física", "teachers": [
+                    {"name": "Bruno", "grades": [{"name": "1B", "labs": ["L1", "L2", "L3"]}]},
+                    {"name": "Carla", "grades": [{"name": "1C", "labs": ["L2", "L1"]}]},
+                ]},
+            ],
+            "caps": {"lab_per_day": {"L3": 1}},
+        }));
+        let solved = Solver::new(&school, &rules).solve();
+        for objective in [Objective::Total, Objective::Fair] {
+            for seed in 0..4 {
+                let improvement = Improvement {
+                    time: Duration::from_millis(50),
+                    seed,
+                    objective,
+                };
+                let improved = Solver::new(&school, &rules).improve(improvement).solve();
+                assert_eq!(verify(&school, &rules, &improved), []);
+                assert!(improved.slotted.len() >= solved.slotted.len());
+                // Being fair can take first choices from some to give them to others
+                if objective == Objective::Total {
+                    assert!(improved.warnings.len() <= solved.warnings.len());
+                }
+            }
+        }
+    }
+}
@@ -1,29 +1,92 @@
 mod backtrack;
+pub mod cancel;
+mod capacity;
+mod caps;
+pub mod external;
+mod improve;
+mod sat;
+pub mod saved;
 pub mod solution;
+pub mod verify;
 
 use crate::{
-    rules::Rules,
-    school::{Class, ClassRef, Grade, Laboratory, School, Slot, Subject, Teacher, Time},
+    rules::{Period, Rules, Split},
+    school::{Class, Grade, Laboratory, School, Slot, Subject, Teacher, Time},
+};
+use backtrack::Outcome;
+use cancel::CancelToken;
+use capacity::Capacity;
+use caps::Limits;
+pub use improve::{Improvement, Objective};
+use solution::{Error, LabSlottedClass, Solution, Statistics, Warning};
+use std::{
+    cell::Cell,
+    collections::{BTreeMap, BTreeSet, HashMap},
+    ops::ControlFlow,
+    str::FromStr,
+    time::Instant,
 };
-use solution::{Error, LabSlottedClass, Solution, Warning};
-use std::collections::{BTreeMap, HashMap};
 
 #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 struct LabSlotId {
     lab: usize,
     slot: usize,
+    /// The week of the cycle, 0 being week A
+    week: usize,
 }
 
 #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 struct ClassId(usize);
 
+/// The lab slots a class takes, one for each period of its session in each week it meets,
+/// sorted
+type Placement = Vec<LabSlotId>;
+
+/// Which classes are in each lab slot
+type Decided = BTreeMap<LabSlotId, Vec<ClassId>>;
+
+/// Where each class is
+fn placements(decided: &Decided) -> BTreeMap<ClassId, Placement> {
+    let mut placements: BTreeMap<ClassId, Placement> = BTreeMap::new();
+    for (slot, classes) in decided {
+        for class in classes {
+            placements.entry(*class).or_default().push(*slot);
+        }
+    }
+    placements
+}
+
+/// Which classes are in each lab slot, from where each class is
+fn decided<'p>(placements: impl IntoIterator<Item = (ClassId, &'p Placement)>) -> Decided {
+    let mut decided: Decided = BTreeMap::new();
+    for (class, placement) in placements {
+        for slot in placement {
+            decided.entry(*slot).or_default().push(class);
+        }
+    }
+    decided
+}
+
 struct State<'a> {
-    decided: BTreeMap<LabSlotId, ClassId>,
+    decided: Decided,
     remaining: &'a [ClassId],
 }
 
 struct Context {
-    slots_of: BTreeMap<ClassId, Vec<LabSlotId>>,
+    /// Where each class can go, in the order to try them
+    slots_of: BTreeMap<ClassId, Vec<Placement>>,
+    /// How bad each placement in `slots_of` is for its class, see [`Problem::cost`]
+    costs: BTreeMap<ClassId, Vec<usize>>,
+    /// Previous solutions the goal must differ from
+    avoid: Vec<BTreeMap<ClassId, Placement>>,
+    /// How many classes must be placed differently than in each of `avoid`
+    min_difference: usize,
+    /// A previous solution the goal should change as little as possible
+    previous: BTreeMap<ClassId, Placement>,
+    /// Fewest changes to `previous` found so far, states with as many are pruned
+    best_changes: Cell<usize>,
+    limits: Limits,
+    capacity: Capacity,
 }
 
 impl<'a> backtrack::State<Context> for State<'a> {
@@ -32,15 +95,20 @@ impl<'a> backtrack::State<Context> for State<'a> {
             [head, remaining @ ..] => Some(
                 ctx.slots_of[head]
                     .iter()
-                    .filter(|slot| !self.decided.contains_key(slot))
-                    .map(|slot| {
+                    .filter(|placement| ctx.capacity.has_room(&self.decided, *head, placement))
+                    .filter(|placement| ctx.limits.allows(&self.decided, *head, placement))
+                    .map(|placement| {
                         let mut map = self.decided.clone();
-                        map.insert(slot.clone(), *head);
+                        for &slot in placement {
+                            map.entry(slot).or_default().push(*head);
+                        }
                         State {
                             decided: map,
                             remaining,
                         }
-                    }),
+                    })
+                    .filter(|state| state.can_differ(ctx))
+                    .filter(|state| state.changes(ctx) < ctx.best_changes.get()),
             )
             .into_iter()
             .flatten(),
@@ -53,158 +121,1080 @@ impl<'a> backtrack::State<Context> for State<'a> {
     }
 }
 
+impl State<'_> {
+    /// Whether the remaining classes can still make this state different enough from the
+    /// solutions to avoid
+    fn can_differ(&self, ctx: &Context) -> bool {
+        if ctx.avoid.is_empty() {
+            return true;
+        }
+        let placements = placements(&self.decided);
+        ctx.avoid.iter().all(|previous| {
+            let differing = placements
+                .iter()
+                .filter(|(class, placement)| previous.get(class) != Some(placement))
+                .count();
+            differing + self.remaining.len() >= ctx.min_difference
+        })
+    }
+
+    /// How many classes were placed differently than in the previous solution
+    fn changes(&self, ctx: &Context) -> usize {
+        if ctx.previous.is_empty() {
+            return 0;
+        }
+        placements(&self.decided)
+            .iter()
+            .filter(|(class, placement)| ctx.previous.get(class).is_some_and(|p| p != *placement))
+            .count()
+    }
+}
+
+fn into_slotted(decided: Decided) -> Vec<LabSlottedClass> {
+    decided
+        .into_iter()
+        .flat_map(|(LabSlotId { slot, lab, week }, classes)| {
+            (classes.into_iter()).map(move |ClassId(class)| LabSlottedClass {
+                lab,
+                slot,
+                week,
+                class,
+            })
+        })
+        .collect()
+}
+
+/// How the solver searches for a placement of the classes
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Backend {
+    /// Depth-first search over the classes, trying each of their lab slots in order
+    #[default]
+    Backtracking,
+    /// Encode the placement as a boolean formula and hand it to a SAT solver
+    Sat,
+}
+
+impl Backend {
+    fn search(
+        self,
+        ctx: &Context,
+        remaining: &[ClassId],
+        tracker: &mut Tracker,
+    ) -> Outcome<Decided> {
+        match self {
+            Backend::Backtracking => backtracking(ctx, remaining, tracker),
+            Backend::Sat => sat::search(ctx, remaining, tracker),
+        }
+    }
+}
+
+impl FromStr for Backend {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "backtracking" => Ok(Backend::Backtracking),
+            "sat" => Ok(Backend::Sat),
+            _ => Err(format!("Unknown backend: {s}")),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct SolveError;
 
+/// Called with the current statistics while the solver runs
+pub type Progress<'a> = Box<dyn FnMut(&Statistics) + 'a>;
+
+/// How many states are visited between progress reports
+const PROGRESS_INTERVAL: u64 = 4096;
+
+struct Tracker<'s, 'p> {
+    statistics: Statistics,
+    started: Instant,
+    progress: &'s mut Option<Progress<'p>>,
+    cancel: Option<&'s CancelToken>,
+}
+
+impl Tracker<'_, '_> {
+    fn report(&mut self) {
+        self.statistics.elapsed = self.started.elapsed();
+        if let Some(progress) = self.progress {
+            progress(&self.statistics);
+        }
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.cancel.is_some_and(|c| c.is_cancelled())
+    }
+}
+
+impl backtrack::Monitor for Tracker<'_, '_> {
+    fn visit(&mut self) -> ControlFlow<()> {
+        self.statistics.nodes += 1;
+        if self.statistics.nodes.is_multiple_of(PROGRESS_INTERVAL) {
+            self.report();
+        }
+        if self.is_cancelled() {
+            ControlFlow::Break(())
+        } else {
+            ControlFlow::Continue(())
+        }
+    }
+
+    fn backtrack(&mut self) {
+        self.statistics.backtracks += 1;
+    }
+}
+
+/// The classes to place, with names from the rules already resolved
+#[derive(Clone)]
+struct Problem {
+    /// Each class with its lab choices, in order of preference
+    classes: Vec<(usize, Vec<usize>)>,
+    forbidden_times: HashMap<usize, Vec<Time>>,
+    /// Times each teacher can't have lab classes at
+    unavailable: HashMap<usize, Vec<Period>>,
+    /// Times each teacher would rather have lab classes at
+    preferred: HashMap<usize, Vec<Period>>,
+    /// Classes that take two adjacent periods in the same lab
+    double: BTreeSet<usize>,
+    /// How many weeks the schedule takes to repeat
+    weeks: usize,
+    /// Classes that meet only once every this many weeks
+    every: BTreeMap<usize, usize>,
+    /// Classes split into two half-groups in two labs at once, with the teacher of the second
+    /// half-group when it's another one
+    split: BTreeMap<usize, Option<usize>>,
+    limits: Limits,
+    capacity: Capacity,
+    /// Where each class was in a previous solution that should be kept as much as possible
+    previous: BTreeMap<ClassId, Placement>,
+}
+
+impl Problem {
+    fn is_forbidden(&self, school: &School, LabSlotId { lab, slot, .. }: LabSlotId) -> bool {
+        let slot: &Slot = school.get(slot);
+        self.forbidden_times
+            .get(&lab)
+            .map(|v| v.contains(&slot.time))
+            .unwrap_or(false)
+    }
+
+    /// Whether a teacher of the class can't have it at the slot. The teacher of a second
+    /// half-group also can't be teaching another class then.
+    fn is_unavailable(&self, school: &School, class: usize, slot_id: usize) -> bool {
+        let teacher = school.classes.get(class).unwrap().teacher;
+        let second = self.split.get(&class).copied().flatten();
+        let slot: &Slot = school.get(slot_id);
+        let unavailable = |teacher| {
+            (self.unavailable.get(&teacher))
+                .is_some_and(|periods| periods.iter().any(|p| p.contains(slot)))
+        };
+        unavailable(teacher)
+            || second
+                .is_some_and(|second| unavailable(second) || school.teaches_at(second, slot_id))
+    }
+
+    /// Whether the class's teacher would rather have it at another time
+    fn is_undesired_time(&self, school: &School, class: usize, slot: usize) -> bool {
+        let teacher = school.classes.get(class).unwrap().teacher;
+        let slot: &Slot = school.get(slot);
+        self.preferred
+            .get(&teacher)
+            .is_some_and(|periods| !periods.is_empty() && !periods.iter().any(|p| p.contains(slot)))
+    }
+
+    /// How bad a placement is for the class: the position of the lab in its choices, or of the
+    /// worse one of a split class, plus one when its teacher would rather have it at another
+    /// time. A lab that is not a choice at all ranks after all of them.
+    fn cost(&self, school: &School, class: ClassId, placement: &[LabSlotId]) -> usize {
+        let labs = &self.classes.iter().find(|c| c.0 == class.0).unwrap().1;
+        let rank = (placement.iter())
+            .map(|s| labs.iter().position(|&l| l == s.lab).unwrap_or(labs.len()))
+            .max()
+            .unwrap();
+        let undesired = (placement.iter()).any(|s| self.is_undesired_time(school, class.0, s.slot));
+        rank + usize::from(undesired)
+    }
+
+    /// The slots the class can have a session at, each with the periods it takes
+    fn sessions(&self, school: &School, class: usize) -> Vec<Vec<usize>> {
+        let slots: Vec<_> = school.slots_of(class).collect();
+        if !self.double.contains(&class) {
+            return slots.into_iter().map(|slot| vec![slot]).collect();
+        }
+        slots
+            .iter()
+            .filter_map(|&slot| {
+                let next = school.next_slot(slot)?;
+                slots.contains(&next).then(|| vec![slot, next])
+            })
+            .collect()
+    }
+
+    /// How many of its first lab choices the class takes at once
+    fn labs_at_once(&self, class: usize) -> usize {
+        if self.split.contains_key(&class) {
+            2
+        } else {
+            1
+        }
+    }
+
+    /// The labs the class can take at once: one of the labs, or two different ones for a split
+    /// class, the best ones first
+    fn lab_sets(&self, class: usize, labs: &[usize]) -> Vec<Vec<usize>> {
+        if !self.split.contains_key(&class) {
+            return labs.iter().map(|&lab| vec![lab]).collect();
+        }
+        (0..labs.len())
+            .flat_map(|j| (0..j).map(move |i| vec![labs[i], labs[j]]))
+            .collect()
+    }
+
+    /// Every placement of the class in the labs, before checking when the labs and the teacher
+    /// can be used. A class that meets every few weeks can start at any of the first ones.
+    fn placements(&self, school: &School, class: usize, labs: &[usize]) -> Vec<Placement> {
+        let every = self.every.get(&class).copied().unwrap_or(1);
+        let mut placements = vec![];
+        for session in self.sessions(school, class) {
+            for lab_set in self.lab_sets(class, labs) {
+                for first_week in 0..every.min(self.weeks) {
+                    let mut placement = vec![];
+                    for week in (first_week..self.weeks).step_by(every) {
+                        for &lab in &lab_set {
+                            let slots = session.iter().map(|&slot| LabSlotId { lab, slot, week });
+                            placement.extend(slots);
+                        }
+                    }
+                    placement.sort();
+                    placements.push(placement);
+                }
+            }
+        }
+        placements
+    }
+
+    /// Whether the class can be at the placement when all of its lab choices are considered
+    fn allows(&self, school: &School, class: usize, placement: &[LabSlotId]) -> bool {
+        let Some((_, labs)) = self.classes.iter().find(|c| c.0 == class) else {
+            return false;
+        };
+        (self.placements(school, class, labs).iter()).any(|p| p == placement)
+            && placement.iter().all(|&s| {
+                !self.is_forbidden(school, s)
+                    && !self.is_unavailable(school, class, s.slot)
+                    && self.capacity.fits(s.lab, &[ClassId(class)])
+            })
+    }
+}
+
+/// Lists where each class can go when considering its first `relax` lab choices, or as many
+/// more as it takes for the class to have somewhere to go. Times the teacher prefers are tried
+/// before the others, and where the class was in the previous solution before anything else, as
+/// long as the rules still allow it.
+fn candidates(school: &School, problem: &Problem, relax: usize) -> (Context, Vec<ClassId>) {
+    let mut slots_of = BTreeMap::new();
+    let mut costs = BTreeMap::new();
+    let mut remaining = Vec::new();
+    for (class, labs) in problem.classes.iter() {
+        let usable = |relax: usize| -> Vec<Placement> {
+            let labs = &labs[..relax.min(labs.len())];
+            (problem.placements(school, *class, labs).into_iter())
+                .filter(|placement| {
+                    placement.iter().all(|&slot| {
+                        !problem.is_forbidden(school, slot)
+                            && !problem.is_unavailable(school, *class, slot.slot)
+                            && problem.capacity.fits(slot.lab, &[ClassId(*class)])
+                    })
+                })
+                .collect()
+        };
+        let mut relax = relax.max(problem.labs_at_once(*class));
+        let mut slots = usable(relax);
+        while slots.is_empty() && relax < labs.len() {
+            relax += 1;
+            slots = usable(relax);
+        }
+        slots.sort_by_key(|placement| {
+            (placement.iter()).any(|s| problem.is_undesired_time(school, *class, s.slot))
+        });
+        if let Some(previous) = problem.previous.get(&ClassId(*class)) {
+            if problem.allows(school, *class, previous) {
+                slots.retain(|placement| placement != previous);
+                slots.insert(0, previous.clone());
+            }
+        }
+        if !slots.is_empty() {
+            let cost = |placement: &Placement| problem.cost(school, ClassId(*class), placement);
+            costs.insert(ClassId(*class), slots.iter().map(cost).collect());
+            slots_of.insert(ClassId(*class), slots);
+            remaining.push(ClassId(*class));
+        }
+    }
+    let ctx = Context {
+        slots_of,
+        costs,
+        avoid: vec![],
+        min_difference: 0,
+        previous: problem.previous.clone(),
+        best_changes: Cell::new(usize::MAX),
+        limits: problem.limits.clone(),
+        capacity: problem.capacity.clone(),
+    };
+    (ctx, remaining)
+}
+
+/// Warn about every class that did not get its first lab choice, or its first two when split,
+/// once for each lab it got instead
+fn undesired_labs(problem: &Problem, slotted: &[LabSlottedClass]) -> Vec<Warning> {
+    let mut used: BTreeMap<usize, BTreeSet<usize>> = BTreeMap::new();
+    for slotted in slotted {
+        used.entry(slotted.class).or_default().insert(slotted.lab);
+    }
+    let mut warnings = vec![];
+    for (class, used) in used {
+        let Some((_, labs)) = problem.classes.iter().find(|c| c.0 == class) else {
+            continue;
+        };
+        let first_choices = &labs[..problem.labs_at_once(class).min(labs.len())];
+        let mut missed = first_choices.iter().filter(|lab| !used.contains(lab));
+        for &got in used.iter().filter(|lab| !first_choices.contains(lab)) {
+            if let Some(&was) = missed.next() {
+                warnings.push(Warning::UndesiredLab { class, was, got })
+            }
+        }
+    }
+    warnings
+}
+
+/// Warn about every class placed at a time its teacher would rather not have
+fn undesired_times(
+    school: &School,
+    problem: &Problem,
+    slotted: &[LabSlottedClass],
+) -> Vec<Warning> {
+    let mut seen = BTreeSet::new();
+    slotted
+        .iter()
+        .filter(|s| seen.insert((s.class, s.slot)))
+        .filter(|s| problem.is_undesired_time(school, s.class, s.slot))
+        .map(|s| Warning::UndesiredTime {
+            class: s.class,
+            slot: s.slot,
+        })
+        .collect()
+}
+
+fn warnings(school: &School, problem: &Problem, slotted: &[LabSlottedClass]) -> Vec<Warning> {
+    let mut warnings = undesired_labs(problem, slotted);
+    warnings.extend(undesired_times(school, problem, slotted));
+    warnings.extend(caps::capped(school, problem, slotted));
+    warnings
+}
+
+fn assignment(slotted: &[LabSlottedClass]) -> BTreeMap<ClassId, Placement> {
+    let mut assignment: BTreeMap<ClassId, Placement> = BTreeMap::new();
+    for s in slotted {
+        let slot = LabSlotId {
+            lab: s.lab,
+            slot: s.slot,
+            week: s.week,
+        };
+        assignment.entry(ClassId(s.class)).or_default().push(slot);
+    }
+    for placement in assignment.values_mut() {
+        placement.sort();
+    }
+    assignment
+}
+
+/// Find a goal state. When there is a previous solution, keep searching for the goal that
+/// changes it the least.
+fn backtracking(ctx: &Context, remaining: &[ClassId], tracker: &mut Tracker) -> Outcome<Decided> {
+    let root = State {
+        decided: BTreeMap::new(),
+        remaining,
+    };
+    if ctx.previous.is_empty() {
+        return match backtrack::solve(ctx, root, tracker) {
+            Outcome::Found(s) => Outcome::Found(s.decided),
+            Outcome::Aborted(s) => Outcome::Aborted(s.decided),
+            Outcome::Exhausted => Outcome::Exhausted,
+        };
+    }
+    let mut best = None;
+    let flow = backtrack::search(ctx, root, tracker, &mut |state: State| {
+        let changes = state.changes(ctx);
+        ctx.best_changes.set(changes);
+        best = Some(state.decided);
+        if changes == 0 {
+            ControlFlow::Break(())
+        } else {
+            ControlFlow::Continue(())
+        }
+    });
+    match (flow, best) {
+        // Cancelled, but the best so far is still a complete solution
+        (ControlFlow::Break(Some(_)), Some(best)) => Outcome::Aborted(best),
+        (ControlFlow::Break(Some(partial)), None) => Outcome::Aborted(partial.decided),
+        (_, Some(best)) => Outcome::Found(best),
+        (_, None) => Outcome::Exhausted,
+    }
+}
+
+/// Stops the search at its first dead end
+#[derive(Default)]
+struct Descent {
+    backtracked: bool,
+}
+
+impl backtrack::Monitor for Descent {
+    fn visit(&mut self) -> ControlFlow<()> {
+        if self.backtracked {
+            ControlFlow::Break(())
+        } else {
+            ControlFlow::Continue(())
+        }
+    }
+
+    fn backtrack(&mut self) {
+        self.backtracked = true;
+    }
+}
+
+/// The classes the backtracking search places before it first has to backtrack, taking each
+/// one's first placement with room left. Quick, but far from a solution when there is little
+/// room.
+fn descent(ctx: &Context, remaining: &[ClassId]) -> Decided {
+    let root = State {
+        decided: BTreeMap::new(),
+        remaining,
+    };
+    match backtrack::solve(ctx, root, &mut Descent::default()) {
+        Outcome::Found(s) | Outcome::Aborted(s) => s.decided,
+        Outcome::Exhausted => BTreeMap::new(),
+    }
+}
+
 fn solve_for(
     school: &School,
-    mut classes: Vec<(usize, Vec<usize>)>,
-    forbidden_times: &HashMap<usize, Vec<Time>>,
+    mut problem: Problem,
+    backend: Backend,
+    tracker: &mut Tracker,
 ) -> Solution {
-    let mut solution = Solution {
-        slotted: vec![],
-        errors: vec![],
-        warnings: vec![],
-    };
-    let max_relax = classes.iter().map(|c| c.1.len()).max().unwrap_or_default();
+    let all = problem.clone();
+    let max_relax = problem
+        .classes
+        .iter()
+        .map(|c| c.1.len())
+        .max()
+        .unwrap_or_default();
     let mut relax = 1;
-    for class in classes.iter().filter(|(_, l)| l.is_empty()) {
-        solution.errors.push(Error::NoLabs(class.0))
-    }
-    classes.retain(|c| !c.1.is_empty());
-    while !classes.is_empty() {
-        let mut slots_of = BTreeMap::new();
-        let mut remaining = Vec::new();
-        for (class, labs) in classes.iter() {
-            let slots: Vec<_> = school
-                .slots_of(*class)
-                .flat_map(|slot| {
-                    labs.iter()
-                        .take(relax)
-                        .map(move |&lab| LabSlotId { lab, slot })
-                        .filter(|LabSlotId { lab, slot }| {
-                            let slot: &Slot = school.get(*slot);
-                            !forbidden_times
-                                .get(lab)
-                                .map(|v| v.contains(&slot.time))
-                                .unwrap_or(false)
-                        })
-                })
-                .collect();
-            if slots.len() > 0 {
-                slots_of.insert(ClassId(*class), slots);
-                remaining.push(ClassId(*class));
+    let mut slotted = vec![];
+    let mut errors = vec![];
+    let mut cancelled = false;
+    for class in problem.classes.iter().filter(|(_, l)| l.is_empty()) {
+        errors.push(Error::NoLabs(class.0))
+    }
+    problem.classes.retain(|c| !c.1.is_empty());
+    while !problem.classes.is_empty() {
+        tracker.statistics.relaxations = relax;
+        tracker.report();
+        let (ctx, remaining) = candidates(school, &problem, relax);
+        let final_state = backend.search(&ctx, &remaining, tracker);
+        match final_state {
+            Outcome::Found(s) => {
+                slotted = into_slotted(s);
+                break;
             }
-        }
-        let final_state = backtrack::solve(
-            &Context { slots_of },
-            State {
-                decided: BTreeMap::new(),
-                remaining: &remaining,
-            },
-        );
-        if let Some(s) = final_state {
-            solution.slotted = s
-                .decided
-                .into_iter()
-                .map(
-                    |(LabSlotId { slot, lab }, ClassId(class))| LabSlottedClass {
-                        lab,
-                        slot,
-                        class,
-                    },
-                )
-                .collect::<Vec<_>>();
-            break;
+            Outcome::Aborted(s) => {
+                slotted = into_slotted(s);
+                cancelled = true;
+                break;
+            }
+            Outcome::Exhausted => {}
         }
         if relax < max_relax {
-            eprintln!("Failed to use {relax}-th lab choices, relaxing");
             relax += 1;
-        } else if remaining.len() > 0 {
+        } else if !remaining.is_empty() {
             let first_considered = remaining[0].0;
-            let class: ClassRef = school.get(first_considered);
-            eprintln!("Failed to solve with max relaxation, dropping class {class}");
-            solution.errors.push(Error::Missing(first_considered));
-            classes.retain(|(c, _)| *c != first_considered);
+            tracker.statistics.dropped += 1;
+            errors.push(Error::Missing(first_considered));
+            problem.classes.retain(|(c, _)| *c != first_considered);
         } else {
             break;
         }
     }
-    for slotted in solution.slotted.iter() {
-        let first_choice = classes
-            .iter()
-            .find(|c| c.0 == slotted.class)
-            .map(|c| c.1.get(0))
-            .flatten();
-        if let Some(first_choice) = first_choice {
-            if *first_choice != slotted.lab {
-                solution.warnings.push(Warning::UndesiredLab {
-                    class: (slotted.class),
-                    was: *first_choice,
-                    got: slotted.lab,
-                })
-            }
-        }
+    if !cancelled {
+        // Classes that had nowhere to go, like a double session without adjacent periods
+        let unplaced = (problem.classes.iter())
+            .filter(|(class, _)| !slotted.iter().any(|s| s.class == *class))
+            .map(|(class, _)| Error::Missing(*class));
+        errors.extend(unplaced.collect::<Vec<_>>());
+    }
+    tracker.report();
+    Solution {
+        warnings: warnings(school, &all, &slotted),
+        slotted,
+        errors,
+        statistics: std::mem::take(&mut tracker.statistics),
+        cancelled,
+        moved: vec![],
     }
-    solution
 }
 
-pub fn solve(school: &School, rules: &Rules) -> Solution {
-    let mut classes = vec![];
-    let mut forbidden = HashMap::new();
-    for (lab_name, times) in rules.forbidden_times.iter() {
-        let Some(lab_id) = school.labs.find_key(&Laboratory {
-            name: lab_name.clone(),
-        }) else {
-            eprintln!("Lab does not exist: {}", lab_name);
-            continue;
-        };
-        forbidden.insert(lab_id, times.clone());
+/// Look for more solutions to the same problem the first one solved, each differing from all
+/// the previous ones in at least `min_difference` classes. Once there are no more among the lab
+/// choices the first one considered, later choices are considered too.
+fn alternatives_for(
+    school: &School,
+    first: Solution,
+    mut problem: Problem,
+    count: usize,
+    min_difference: usize,
+    backend: Backend,
+    tracker: &mut Tracker,
+) -> Vec<Solution> {
+    if first.cancelled {
+        return vec![first];
     }
-    for class in &rules.classes {
-        let Some(subject_id) = school.subjects.find_key(&Subject {
-            name: class.subject.clone(),
-        }) else {
-            eprintln!("Subject does not exist: {}", class.subject);
-            continue;
+    problem.classes.retain(|(c, _)| {
+        !first.errors.iter().any(|e| match e {
+            Error::Missing(class) | Error::NoLabs(class) => class == c,
+        })
+    });
+    let max_relax = problem.classes.iter().map(|c| c.1.len()).max();
+    let mut relax = first.statistics.relaxations;
+    let (mut ctx, mut remaining) = candidates(school, &problem, relax);
+    ctx.min_difference = min_difference.max(1);
+    ctx.avoid.push(assignment(&first.slotted));
+    let errors = first.errors.clone();
+    let mut solutions = vec![first];
+    while solutions.len() < count {
+        tracker.statistics = Statistics {
+            relaxations: relax,
+            dropped: solutions[0].statistics.dropped,
+            ..Default::default()
+        };
+        tracker.started = Instant::now();
+        // Each alternative is the one closest to the previous solution on its own
+        ctx.best_changes.set(usize::MAX);
+        let s = match backend.search(&ctx, &remaining, tracker) {
+            Outcome::Found(s) => s,
+            Outcome::Exhausted if relax < max_relax.unwrap_or_default() => {
+                relax += 1;
+                let (wider, wider_remaining) = candidates(school, &problem, relax);
+                ctx = Context {
+                    avoid: std::mem::take(&mut ctx.avoid),
+                    min_difference: ctx.min_difference,
+                    ..wider
+                };
+                remaining = wider_remaining;
+                continue;
+            }
+            Outcome::Exhausted | Outcome::Aborted(_) => break,
         };
-        for teacher in &class.teachers {
+        let slotted = into_slotted(s);
+        ctx.avoid.push(assignment(&slotted));
+        tracker.report();
+        solutions.push(Solution {
+            warnings: warnings(school, &problem, &slotted),
+            slotted,
+            errors: errors.clone(),
+            statistics: std::mem::take(&mut tracker.statistics),
+            cancelled: false,
+            moved: vec![],
+        });
+    }
+    solutions
+}
+
+/// Configures a solve before running it
+pub struct Solver<'a> {
+    school: &'a School,
+    rules: &'a Rules,
+    progress: Option<Progress<'a>>,
+    cancel: Option<CancelToken>,
+    previous: Option<&'a Solution>,
+    backend: Backend,
+    improvement: Option<Improvement>,
+}
+
+impl<'a> Solver<'a> {
+    pub fn new(school: &'a School, rules: &'a Rules) -> Self {
+        Self {
+            school,
+            rules,
+            progress: None,
+            cancel: None,
+            previous: None,
+            backend: Backend::default(),
+            improvement: None,
+        }
+    }
+
+    /// Report the statistics periodically while solving
+    pub fn on_progress(mut self, progress: impl FnMut(&Statistics) + 'a) -> Self {
+        self.progress = Some(Box::new(progress));
+        self
+    }
+
+    /// Stop searching once the token is cancelled, keeping whatever was placed so far
+    pub fn cancel_with(mut self, token: CancelToken) -> Self {
+        self.cancel = Some(token);
+        self
+    }
+
+    pub fn backend(mut self, backend: Backend) -> Self {
+        self.backend = backend;
+        self
+    }
+
+    /// Change as few assignments of a previous solution as possible while following the rules,
+    /// reporting which ones moved in [`Solution::moved`]
+    pub fn keep_close_to(mut self, previous: &'a Solution) -> Self {
+        self.previous = Some(previous);
+        self
+    }
+
+    /// After finding a solution, keep moving and swapping classes to get them into labs they
+    /// prefer, without breaking any rule. Only [`Solver::solve`] runs this phase.
+    pub fn improve(mut self, improvement: Improvement) -> Self {
+        self.improvement = Some(improvement);
+        self
+    }
+
+    /// Resolve the names in the rules into the problem to solve, skipping the ones that don't
+    /// exist. [`crate::validate::validate`] reports those.
+    fn resolve(&self) -> Problem {
+        let (school, rules) = (self.school, self.rules);
+        let aliases = &rules.aliases;
+        let mut classes = vec![];
+        let mut double = BTreeSet::new();
+        let mut every = BTreeMap::new();
+        let mut split = BTreeMap::new();
+        let mut forbidden = HashMap::new();
+        for (lab_name, times) in rules.forbidden_times.iter() {
+            let Some(lab_id) = school.labs.find_key(&Laboratory {
+                name: aliases.lab(lab_name),
+            }) else {
+                continue;
+            };
+            forbidden.insert(lab_id, times.clone());
+        }
+        let mut unavailable: HashMap<_, Vec<_>> = HashMap::new();
+        let mut preferred: HashMap<_, Vec<_>> = HashMap::new();
+        for teacher in &rules.teachers {
             let Some(teacher_id) = school.teachers.find_key(&Teacher {
-                name: teacher.name.clone(),
+                name: aliases.teacher(&teacher.name),
             }) else {
-                eprintln!("Teacher does not exist: {}", teacher.name);
                 continue;
             };
-            for grade in &teacher.grades {
-                let Some(grade_id) = school.grades.find_key(&Grade {
-                    name: grade.name.clone(),
-                }) else {
-                    eprintln!("Grade does not exist: {}", grade.name);
-                    continue;
-                };
-                let Some(class_id) = school.classes.find_key(&Class {
-                    teacher: teacher_id,
-                    grade: grade_id,
-                    subject: subject_id,
-                }) else {
-                    eprintln!(
-                        "Class does not exist: {}, {}, {}",
-                        teacher.name, class.subject, grade.name
-                    );
-                    continue;
-                };
-                let mut labs = vec![];
-                for lab in &grade.labs {
-                    let Some(lab_id) = school.labs.find_key(&Laboratory { name: lab.clone() })
-                    else {
-                        eprintln!("Lab does not exist: {}", lab);
-                        continue;
-                    };
-                    labs.push(lab_id);
+            (unavailable.entry(teacher_id).or_default()).extend(&teacher.unavailable);
+            (preferred.entry(teacher_id).or_default()).extend(&teacher.preferred);
+        }
+        for class in rules.expand(school) {
+            let Some(subject_id) = school.subjects.find_key(&Subject {
+                name: aliases.subject(class.subject),
+            }) else {
+                continue;
+            };
+            let Some(teacher_id) = school.teachers.find_key(&Teacher {
+                name: aliases.teacher(class.teacher),
+            }) else {
+                continue;
+            };
+            let Some(grade_id) = school.grades.find_key(&Grade {
+                name: aliases.grade(class.grade),
+            }) else {
+                continue;
+            };
+            let Some(class_id) = school.classes.find_key(&Class {
+                teacher: teacher_id,
+                grade: grade_id,
+                subject: subject_id,
+            }) else {
+                continue;
+            };
+            let labs = class
+                .labs
+                .iter()
+                .filter_map(|lab| {
+                    school.labs.find_key(&Laboratory {
+                        name: aliases.lab(lab),
+                    })
+                })
+                .collect();
+            classes.push((class_id, labs));
+            if class.double {
+                double.insert(class_id);
+            }
+            if class.every > 1 {
+                every.insert(class_id, class.every);
+            }
+            if let Some(Split { teacher }) = class.split {
+                // An unknown second teacher is left out, like any other unknown name, and so is
+                // the class's own teacher, who takes both half-groups
+                let second = (teacher.as_ref())
+                    .and_then(|name| {
+                        school.teachers.find_key(&Teacher {
+                            name: aliases.teacher(name),
+                        })
+                    })
+                    .filter(|&second| second != teacher_id);
+                split.insert(class_id, second);
+            }
+        }
+        Problem {
+            classes,
+            forbidden_times: forbidden,
+            unavailable,
+            preferred,
+            double,
+            weeks: rules.weeks.max(1),
+            every,
+            limits: Limits::new(school, &rules.caps, aliases, rules.weeks.max(1), &split),
+            capacity: Capacity::new(school, &rules.labs, &rules.sizes, aliases, &split),
+            split,
+            previous: self
+                .previous
+                .map(|p| assignment(&p.slotted))
+                .unwrap_or_default(),
+        }
+    }
+
+    fn tracker(&mut self) -> Tracker<'_, 'a> {
+        Tracker {
+            statistics: Statistics::default(),
+            started: Instant::now(),
+            progress: &mut self.progress,
+            cancel: self.cancel.as_ref(),
+        }
+    }
+
+    fn report_moves(&self, solution: &mut Solution) {
+        if let Some(previous) = self.previous {
+            solution.moved = solution.moves_from(previous);
+        }
+    }
+
+    pub fn solve(mut self) -> Solution {
+        let problem = self.resolve();
+        let (school, backend, improvement) = (self.school, self.backend, self.improvement);
+        let mut tracker = self.tracker();
+        let mut solution = solve_for(school, problem.clone(), backend, &mut tracker);
+        if let Some(improvement) = improvement.filter(|_| !solution.cancelled) {
+            solution = improve::improve(school, &problem, solution, improvement, &mut tracker);
+        }
+        self.report_moves(&mut solution);
+        solution
+    }
+
+    /// Find up to `count` solutions, each placing at least `min_difference` classes differently
+    /// than all the others. The first one is the same that [`Solver::solve`] finds, and the
+    /// others use lab choices further down the list once the ones it needed run out.
+    pub fn solve_many(mut self, count: usize, min_difference: usize) -> Vec<Solution> {
+        let problem = self.resolve();
+        let (school, backend) = (self.school, self.backend);
+        let mut tracker = self.tracker();
+        let first = solve_for(school, problem.clone(), backend, &mut tracker);
+        let mut solutions = alternatives_for(
+            school,
+            first,
+            problem,
+            count,
+            min_difference,
+            backend,
+            &mut tracker,
+        );
+        for solution in solutions.iter_mut() {
+            self.report_moves(solution);
+        }
+        solutions
+    }
+}
+
+/// Solve with the default settings. See [`Solver::cancel_with`] to be able to stop early.
+pub fn solve(school: &School, rules: &Rules) -> Solution {
+    Solver::new(school, rules).solve()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::{school::Day::Monday, testing};
+
+    /// The lab each class was placed in, sorted by class
+    fn labs_of(solution: &Solution) -> Vec<(usize, usize)> {
+        let mut labs: Vec<_> = solution.slotted.iter().map(|s| (s.class, s.lab)).collect();
+        labs.sort();
+        labs
+    }
+
+    #[test]
+    fn backends_agree_on_preferred_labs() {
+        let school = testing::school(
+            &[
+                ("1A", "Química", "Ana", Monday, "7:30"),
+                ("1B", "Química", "Ana", Monday, "8:20"),
+                ("1B", "Física", "Bruno", Monday, "7:30"),
+                ("1C", "Física", "Carla", Monday, "7:30"),
+            ],
+            &["L1", "L2", "L3"],
+        );
+        let rules = testing::rules(json!({
+            "classes": [
+                {"subject": "Química", "teachers": [
+                    {"name": "Ana", "grades": [
+                        {"name": "1A", "labs": ["L1", "L2"]},
+                        {"name": "1B", "labs": ["L3"]},
+                    ]},
+                ]},
+                {"subject": "Física", "teachers": [
+                    {"name": "Bruno", "grades": [{"name": "1B", "labs": ["L1", "L2", "L3"]}]},
+                    {"name": "Carla", "grades": [{"name": "1C", "labs": ["L2", "L1"]}]},
+                ]},
+            ],
+        }));
+        let backtracking = Solver::new(&school, &rules).solve();
+        let sat = Solver::new(&school, &rules).backend(Backend::Sat).solve();
+        assert!(backtracking.errors.is_empty());
+        assert!(sat.errors.is_empty());
+        assert_eq!(labs_of(&backtracking), labs_of(&sat));
+        assert_eq!(backtracking.warnings.len(), sat.warnings.len());
+        let physics = testing::class(&school, "1B", "Física", "Bruno");
+        let l3 = testing::lab(&school, "L3");
+        assert!(labs_of(&sat).contains(&(physics, l3)));
+    }
+
+    #[test]
+    fn sat_keeps_a_partial_placement_when_cancelled_early() {
+        let school = testing::school(&[("1A", "Química", "Ana", Monday, "7:30")], &["L1"]);
+        let rules = testing::rules(json!({
+            "classes": [{"subject": "Química", "teachers": [
+                {"name": "Ana", "grades": [{"name": "1A", "labs": ["L1"]}]},
+            ]}],
+        }));
+        let cancel = CancelToken::new();
+        cancel.cancel();
+        let solution = Solver::new(&school, &rules)
+            .backend(Backend::Sat)
+            .cancel_with(cancel)
+            .solve();
+        assert!(solution.cancelled);
+        assert_eq!(solution.slotted.len(), 1);
+    }
+
+    #[test]
+    fn falls_back_on_later_labs_when_the_first_choice_cant_host_the_class() {
+        let school = testing::school(
+            &[
+                ("1A", "Química", "Ana", Monday, "7:30"),
+                ("1B", "Química", "Bia", Monday, "8:20"),
+            ],
+            &["Maker", "L2"],
+        );
+        let rules = testing::rules(json!({
+            "classes": [{"subject": "Química", "teachers": [
+                {"name": "Ana", "grades": [{"name": "1A", "labs": ["Maker", "L2"]}]},
+                {"name": "Bia", "grades": [{"name": "1B", "labs": ["Maker"]}]},
+            ]}],
+            "labs": {"Maker": {"seats": 20}},
+            "sizes": {"1A": 30},
+        }));
+        let a = testing::class(&school, "1A", "Química", "Ana");
+        let b = testing::class(&school, "1B", "Química", "Bia");
+        let (maker, l2) = (testing::lab(&school, "Maker"), testing::lab(&school, "L2"));
+        for backend in [Backend::Backtracking, Backend::Sat] {
+            let solution = Solver::new(&school, &rules).backend(backend).solve();
+            assert!(solution.errors.is_empty());
+            assert_eq!(labs_of(&solution), [(a, l2), (b, maker)]);
+        }
+    }
+
+    #[test]
+    fn finds_every_alternative_close_to_a_previous_solution() {
+        let school = testing::school(
+            &[
+                ("1A", "Química", "Ana", Monday, "7:30"),
+                ("1B", "Química", "Bia", Monday, "8:20"),
+                ("1C", "Química", "Caio", Monday, "9:10"),
+            ],
+            &["L1"],
+        );
+        let rules = testing::rules(json!({
+            "classes": [{"subject": "Química", "teachers": [
+                {"name": "Ana", "grades": [{"name": "1A", "labs": ["L1"], "every": 2}]},
+                {"name": "Bia", "grades": [{"name": "1B", "labs": ["L1"], "every": 2}]},
+                {"name": "Caio", "grades": [{"name": "1C", "labs": ["L1"], "every": 2}]},
+            ]}],
+            "weeks": 2,
+        }));
+        let previous = Solver::new(&school, &rules).solve();
+        let alone = Solver::new(&school, &rules).solve_many(5, 1);
+        let close = Solver::new(&school, &rules)
+            .keep_close_to(&previous)
+            .solve_many(5, 1);
+        assert_eq!(alone.len(), 5);
+        assert_eq!(close.len(), 5);
+        assert!(close[0].moved.is_empty());
+    }
+
+    #[test]
+    fn splits_a_class_whose_second_teacher_is_its_own() {
+        let school = testing::school(&[("1A", "Química", "Ana", Monday, "7:30")], &["L1", "L2"]);
+        let rules = testing::rules(json!({
+            "classes": [{"subject": "Química", "teachers": [
+                {"name": "Ana", "grades": [
+                    {"name": "1A", "labs": ["L1", "L2"], "split": {"teacher": "Ana"}},
+                ]},
+            ]}],
+        }));
+        let class = testing::class(&school, "1A", "Química", "Ana");
+        let (l1, l2) = (testing::lab(&school, "L1"), testing::lab(&school, "L2"));
+        let solution = Solver::new(&school, &rules).solve();
+        assert!(solution.errors.is_empty());
+        assert_eq!(labs_of(&solution), [(class, l1), (class, l2)]);
+    }
+
+    #[test]
+    fn backtracking_keeps_a_partial_placement_when_cancelled() {
+        // One class more than there are labs, all at once, takes long to rule out
+        let grades = ["1A", "1B", "1C", "1D", "1E", "1F", "1G", "1H", "1I"];
+        let teachers = [
+            "Ana", "Bia", "Caio", "Davi", "Eva", "Fabio", "Gil", "Hugo", "Iris",
+        ];
+        let periods: Vec<_> = (grades.iter().zip(teachers))
+            .map(|(grade, teacher)| (*grade, "Química", teacher, Monday, "7:30"))
+            .collect();
+        let labs = ["L1", "L2", "L3", "L4", "L5", "L6", "L7", "L8"];
+        let school = testing::school(&periods, &labs);
+        let rules = testing::rules(json!({
+            "classes": [{"subject": "Química", "teachers": (grades.iter().zip(teachers))
+                .map(|(grade, teacher)| json!({
+                    "name": teacher,
+                    "grades": [{"name": grade, "labs": labs}],
+                }))
+                .collect::<Vec<_>>()}],
+        }));
+        let cancel = CancelToken::new();
+        let token = cancel.clone();
+        let solution = Solver::new(&school, &rules)
+            .cancel_with(cancel)
+            .on_progress(move |statistics| {
+                if statistics.nodes >= PROGRESS_INTERVAL {
+                    token.cancel();
+                }
+            })
+            .solve();
+        assert!(solution.cancelled);
+        assert!(!solution.slotted.is_empty());
+        assert!(solution.slotted.len() < grades.len());
+        assert!(solution.errors.is_empty());
+    }
+
+    #[test]
+    fn alternatives_differ_enough_and_start_with_the_solution() {
+        let school = testing::school(
+            &[
+                ("1A", "Química", "Ana", Monday, "7:30"),
+                ("1B", "Química", "Bia", Monday, "8:20"),
+            ],
+            &["L1", "L2"],
+        );
+        let rules = testing::rules(json!({
+            "classes": [{"subject": "Química", "teachers": [
+                {"name": "Ana", "grades": [{"name": "1A", "labs": ["L1", "L2"]}]},
+                {"name": "Bia", "grades": [{"name": "1B", "labs": ["L1", "L2"]}]},
+            ]}],
+        }));
+        let solved = Solver::new(&school, &rules).solve();
+        for (min_difference, count) in [(1, 4), (2, 2)] {
+            let solutions = Solver::new(&school, &rules).solve_many(4, min_difference);
+            assert_eq!(solutions.len(), count);
+            assert_eq!(labs_of(&solutions[0]), labs_of(&solved));
+            for (i, a) in solutions.iter().enumerate() {
+                for b in &solutions[..i] {
+                    let (a, b) = (labs_of(a), labs_of(b));
+                    let differing = a.iter().filter(|placed| !b.contains(placed)).count();
+                    assert!(differing >= min_difference);
                 }
-                classes.push((class_id, labs));
             }
         }
     }
-    solve_for(school, classes, &forbidden)
+
+    #[test]
+    fn places_both_periods_of_a_double_class_in_one_lab() {
+        let school = testing::school(
+            &[
+                ("1A", "Química", "Ana", Monday, "7:30"),
+                ("1A", "Química", "Ana", Monday, "8:20"),
+                ("1B", "Física", "Bia", Monday, "8:20"),
+            ],
+            &["L1", "L2"],
+        );
+        let rules = testing::rules(json!({
+            "classes": [
+                {"subject": "Química", "teachers": [
+                    {"name": "Ana", "grades": [{"name": "1A", "labs": ["L1", "L2"], "double": true}]},
+                ]},
+                {"subject": "Física", "teachers": [
+                    {"name": "Bia", "grades": [{"name": "1B", "labs": ["L1"]}]},
+                ]},
+            ],
+        }));
+        let chemistry = testing::class(&school, "1A", "Química", "Ana");
+        let physics = testing::class(&school, "1B", "Física", "Bia");
+        let (l1, l2) = (testing::lab(&school, "L1"), testing::lab(&school, "L2"));
+        for backend in [Backend::Backtracking, Backend::Sat] {
+            let solution = Solver::new(&school, &rules).backend(backend).solve();
+            assert!(solution.errors.is_empty());
+            // L1 is free at 7:30, but not for the second period
+            assert_eq!(
+                labs_of(&solution),
+                [(chemistry, l2), (chemistry, l2), (physics, l1)]
+            );
+        }
+    }
+
+    #[test]
+    fn classes_every_other_week_share_a_lab() {
+        let school = testing::school(
+            &[
+                ("1A", "Química", "Ana", Monday, "7:30"),
+                ("1B", "Química", "Bia", Monday, "7:30"),
+            ],
+            &["L1"],
+        );
+        let rules = testing::rules(json!({
+            "classes": [{"subject": "Química", "teachers": [
+                {"name": "Ana", "grades": [{"name": "1A", "labs": ["L1"], "every": 2}]},
+                {"name": "Bia", "grades": [{"name": "1B", "labs": ["L1"], "every": 2}]},
+            ]}],
+            "weeks": 4,
+        }));
+        let a = testing::class(&school, "1A", "Química", "Ana");
+        let b = testing::class(&school, "1B", "Química", "Bia");
+        for backend in [Backend::Backtracking, Backend::Sat] {
+            let solution = Solver::new(&school, &rules).backend(backend).solve();
+            assert!(solution.errors.is_empty());
+            let weeks = |class| {
+                let mut weeks: Vec<_> = (solution.slotted.iter())
+                    .filter(|s| s.class == class)
+                    .map(|s| s.week)
+                    .collect();
+                weeks.sort();
+                weeks
+            };
+            let (weeks_a, weeks_b) = (weeks(a), weeks(b));
+            assert_eq!(weeks_a.len(), 2);
+            assert_eq!(weeks_b.len(), 2);
+            assert!(
+                weeks_a == [0, 2] && weeks_b == [1, 3] || weeks_a == [1, 3] && weeks_b == [0, 2]
+            );
+        }
+    }
 }
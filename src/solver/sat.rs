@@ -0,0 +1,329 @@
+use std::collections::BTreeMap;
+
+use batsat::{lbool, Lit, ProgressStatus, SolverInterface, Var};
+
+use super::{backtrack::Outcome, ClassId, Context, Decided, LabSlotId, Placement, Tracker};
+
+/// A formula in conjunctive normal form, with DIMACS style literals: variables are numbered
+/// from 1 and negative literals are negated variables.
+#[derive(Default)]
+pub(super) struct Cnf {
+    pub vars: i32,
+    pub clauses: Vec<Vec<i32>>,
+}
+
+impl Cnf {
+    pub fn new_var(&mut self) -> i32 {
+        self.vars += 1;
+        self.vars
+    }
+
+    pub fn add(&mut self, clause: Vec<i32>) {
+        self.clauses.push(clause);
+    }
+
+    /// Allow at most `k` of the literals to be true, with a sequential counter
+    pub fn at_most(&mut self, lits: &[i32], k: usize) {
+        let n = lits.len();
+        if k >= n {
+            return;
+        }
+        if k == 0 {
+            for &lit in lits {
+                self.add(vec![-lit]);
+            }
+            return;
+        }
+        // counter[i][j] means at least j + 1 of the first i + 1 literals are true
+        let counter: Vec<Vec<i32>> = (0..n - 1)
+            .map(|_| (0..k).map(|_| self.new_var()).collect())
+            .collect();
+        self.add(vec![-lits[0], counter[0][0]]);
+        for &var in &counter[0][1..] {
+            self.add(vec![-var]);
+        }
+        for i in 1..n - 1 {
+            self.add(vec![-lits[i], counter[i][0]]);
+            self.add(vec![-counter[i - 1][0], counter[i][0]]);
+            for j in 1..k {
+                self.add(vec![-lits[i], -counter[i - 1][j - 1], counter[i][j]]);
+                self.add(vec![-counter[i - 1][j], counter[i][j]]);
+            }
+            self.add(vec![-lits[i], -counter[i - 1][k - 1]]);
+        }
+        self.add(vec![-lits[n - 1], -counter[n - 2][k - 1]]);
+    }
+
+    /// Allow at most `k` of the literals to be true while `guard` is
+    pub fn at_most_if(&mut self, lits: &[i32], k: usize, guard: i32) {
+        let from = self.clauses.len();
+        self.at_most(lits, k);
+        for clause in &mut self.clauses[from..] {
+            clause.push(-guard);
+        }
+    }
+}
+
+/// The placement of the classes as a formula. Variable `i + 1` means the class in `vars[i]`
+/// is placed in its lab slots.
+pub(super) struct Encoding {
+    pub cnf: Cnf,
+    pub vars: Vec<(ClassId, Placement)>,
+    /// How bad the placement of each variable is, see [`super::Problem::cost`]
+    pub costs: Vec<usize>,
+    /// Literals that count the cost of the placements: for each class, one that must be true
+    /// for every unit of the cost of its placement
+    pub ranks: Vec<i32>,
+    /// Index of the clause that places each class in one of its placements
+    pub placed: Vec<usize>,
+}
+
+impl Encoding {
+    pub fn new(ctx: &Context, remaining: &[ClassId]) -> Self {
+        let mut cnf = Cnf::default();
+        let mut vars = vec![];
+        let mut costs = vec![];
+        let mut by_slot: BTreeMap<LabSlotId, Vec<(i32, ClassId)>> = BTreeMap::new();
+        // Allocate the placements first so they are the first variables
+        let by_class: Vec<Vec<i32>> = remaining
+            .iter()
+            .map(|class| {
+                (ctx.slots_of[class].iter())
+                    .zip(&ctx.costs[class])
+                    .map(|(placement, &cost)| {
+                        let lit = cnf.new_var();
+                        vars.push((*class, placement.clone()));
+                        costs.push(cost);
+                        for &slot in placement {
+                            by_slot.entry(slot).or_default().push((lit, *class));
+                        }
+                        lit
+                    })
+                    .collect()
+            })
+            .collect();
+        // Every class goes in exactly one placement, which takes as many of the class's rank
+        // literals as its cost
+        let mut ranks = vec![];
+        let mut placed = vec![];
+        for lits in by_class {
+            let worst = (lits.iter()).map(|&lit| costs[lit as usize - 1]).max();
+            let class_ranks: Vec<i32> = (0..worst.unwrap_or_default())
+                .map(|_| cnf.new_var())
+                .collect();
+            for &lit in &lits {
+                for &rank in &class_ranks[..costs[lit as usize - 1]] {
+                    cnf.add(vec![-lit, rank]);
+                }
+            }
+            ranks.extend(class_ranks);
+            cnf.at_most(&lits, 1);
+            placed.push(cnf.clauses.len());
+            cnf.add(lits);
+        }
+        // Every lab slot holds as many classes and students as its lab takes at once
+        for (slot, options) in &by_slot {
+            let lits: Vec<i32> = options.iter().map(|(lit, _)| *lit).collect();
+            cnf.at_most(&lits, ctx.capacity.concurrent(slot.lab));
+            for overfull in ctx.capacity.overfull(slot.lab, options) {
+                cnf.add(overfull.iter().map(|lit| -lit).collect());
+            }
+        }
+        let options = (vars.iter().enumerate()).map(|(i, (class, p))| (i as i32 + 1, *class, p));
+        for (max, lits) in ctx.limits.groups(options) {
+            cnf.at_most(&lits, max);
+        }
+        let mut encoding = Self {
+            cnf,
+            vars,
+            costs,
+            ranks,
+            placed,
+        };
+        for avoid in &ctx.avoid {
+            let same = encoding.kept(avoid);
+            let allowed = remaining.len().saturating_sub(ctx.min_difference);
+            if remaining.len() < ctx.min_difference {
+                // Not enough classes to differ, make the formula unsatisfiable
+                encoding.cnf.add(vec![]);
+            }
+            encoding.cnf.at_most(&same, allowed);
+        }
+        encoding
+    }
+
+    /// The literals that keep a class where it is in `placement`
+    pub fn kept(&self, placement: &BTreeMap<ClassId, Placement>) -> Vec<i32> {
+        self.vars
+            .iter()
+            .enumerate()
+            .filter(|(_, (class, slots))| placement.get(class) == Some(slots))
+            .map(|(i, _)| i as i32 + 1)
+            .collect()
+    }
+
+    /// How bad the placements chosen by the model are overall
+    pub fn cost(&self, model: impl Fn(i32) -> bool) -> usize {
+        (self.costs.iter().enumerate())
+            .filter(|(i, _)| model(*i as i32 + 1))
+            .map(|(_, cost)| cost)
+            .sum()
+    }
+
+    pub fn decided(&self, model: impl Fn(i32) -> bool) -> Decided {
+        let chosen = (self.vars.iter().enumerate())
+            .filter(|(i, _)| model(*i as i32 + 1))
+            .map(|(_, (class, placement))| (*class, placement));
+        super::decided(chosen)
+    }
+}
+
+struct Callbacks<'t, 's, 'p> {
+    tracker: &'t mut Tracker<'s, 'p>,
+    /// Conflicts counted before this search started
+    conflicts: u64,
+}
+
+impl batsat::Callbacks for Callbacks<'_, '_, '_> {
+    fn on_progress<F>(&mut self, f: F)
+    where
+        F: FnOnce() -> ProgressStatus,
+    {
+        self.tracker.statistics.backtracks = self.conflicts + f().conflicts as u64;
+        self.tracker.report();
+    }
+
+    fn stop(&self) -> bool {
+        self.tracker.is_cancelled()
+    }
+}
+
+type Solver<'t, 's, 'p> = batsat::Solver<Callbacks<'t, 's, 'p>>;
+
+/// Keeps the solver up to date with a formula that only grows
+#[derive(Default)]
+struct Feeder {
+    vars: Vec<Var>,
+    /// How many clauses the solver has already seen
+    fed: usize,
+}
+
+impl Feeder {
+    fn feed(&mut self, solver: &mut Solver, cnf: &Cnf) {
+        while self.vars.len() < cnf.vars as usize {
+            self.vars.push(solver.new_var_default());
+        }
+        for clause in &cnf.clauses[self.fed..] {
+            let mut lits = clause
+                .iter()
+                .map(|&lit| Lit::new(self.vars[lit.unsigned_abs() as usize - 1], lit > 0))
+                .collect();
+            solver.add_clause_reuse(&mut lits);
+        }
+        self.fed = cnf.clauses.len();
+    }
+
+    /// Call the solver assuming `guard` is true, returning `None` if it was interrupted, or
+    /// else the placement it found with its cost
+    fn run(
+        &self,
+        solver: &mut Solver,
+        encoding: &Encoding,
+        guard: Option<i32>,
+    ) -> Option<Option<(Decided, usize)>> {
+        let assumptions: Vec<Lit> = (guard.iter())
+            .map(|&var| Lit::new(self.vars[var as usize - 1], true))
+            .collect();
+        let result = solver.solve_limited(&assumptions);
+        solver.cb_mut().tracker.statistics.nodes += 1;
+        if result == lbool::TRUE {
+            let model = |var: i32| solver.value_var(self.vars[var as usize - 1]) == lbool::TRUE;
+            Some(Some((encoding.decided(model), encoding.cost(model))))
+        } else if result == lbool::FALSE {
+            Some(None)
+        } else {
+            None
+        }
+    }
+}
+
+/// Find a goal state with a SAT solver. Each call to the solver counts as a visited state.
+/// Once there is a solution, keep tightening a bound on its cost until there is none cheaper,
+/// so that classes get the labs they prefer like when backtracking. When there is a previous
+/// solution, the number of changes to it is tightened first.
+///
+/// A bound is only assumed until a solution meets it, so that the formula stays satisfiable
+/// when it can't be met.
+pub(super) fn search(
+    ctx: &Context,
+    remaining: &[ClassId],
+    tracker: &mut Tracker,
+) -> Outcome<Decided> {
+    let mut encoding = Encoding::new(ctx, remaining);
+    let callbacks = Callbacks {
+        conflicts: tracker.statistics.backtracks,
+        tracker,
+    };
+    let mut solver = Solver::new(Default::default(), callbacks);
+    let mut feeder = Feeder::default();
+    let mut best: Option<(Decided, usize, usize)> = None;
+    let kept = encoding.kept(&ctx.previous);
+    let negated: Vec<i32> = kept.iter().map(|lit| -lit).collect();
+    let mut fewer_changes = !ctx.previous.is_empty();
+    let mut guard = None;
+    loop {
+        feeder.feed(&mut solver, &encoding.cnf);
+        let Some(result) = feeder.run(&mut solver, &encoding, guard) else {
+            // Without a solution yet, keep what a quick descent places rather than nothing
+            let partial = match best {
+                Some((best, ..)) => best,
+                None => super::descent(ctx, remaining),
+            };
+            return Outcome::Aborted(partial);
+        };
+        let mut no_fewer_changes = false;
+        match result {
+            Some((decided, cost)) => {
+                if let Some(guard) = guard {
+                    encoding.cnf.add(vec![guard]);
+                }
+                let changed = kept
+                    .iter()
+                    .filter(|&&lit| {
+                        let (class, slots) = &encoding.vars[lit as usize - 1];
+                        (slots.iter())
+                            .any(|slot| !decided.get(slot).is_some_and(|c| c.contains(class)))
+                    })
+                    .count();
+                best = Some((decided, cost, changed));
+            }
+            None if guard.is_none() || !fewer_changes => break,
+            None => no_fewer_changes = true,
+        }
+        let Some((_, cost, changed)) = best else {
+            break;
+        };
+        if fewer_changes && (no_fewer_changes || changed == 0) {
+            // Keep as few changes from now on, and look for better labs
+            encoding.cnf.at_most(&negated, changed);
+            fewer_changes = false;
+        }
+        let next = encoding.cnf.new_var();
+        if fewer_changes {
+            // Look for a solution that keeps more of the previous one
+            encoding.cnf.at_most_if(&negated, changed - 1, next);
+        } else if cost > 0 {
+            encoding.cnf.at_most_if(&encoding.ranks, cost - 1, next);
+        } else {
+            break;
+        }
+        guard = Some(next);
+    }
+    let conflicts = solver.num_conflicts();
+    let callbacks = solver.cb_mut();
+    callbacks.tracker.statistics.backtracks = callbacks.conflicts + conflicts;
+    match best {
+        Some((best, ..)) => Outcome::Found(best),
+        None => Outcome::Exhausted,
+    }
+}
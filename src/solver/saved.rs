@@ -0,0 +1,504 @@
+use std::{
+    fmt::Display,
+    fs::{self, File},
+    io::BufReader,
+    path::Path,
+};
+
+use anyhow::{anyhow, bail};
+use serde::{Deserialize, Serialize};
+
+use super::solution::{Cap, Error, LabSlot, LabSlottedClass, Move, Solution, Statistics, Warning};
+use crate::school::{Class, ClassRef, Day, Grade, Laboratory, School, Slot, Subject, Teacher};
+
+/// The version of the saved solution format written by this program. Files saved before the
+/// version was written are also version 1.
+pub const VERSION: u32 = 1;
+
+/// A class, by the names of its teacher, grade and subject
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct SavedClass {
+    pub teacher: String,
+    pub grade: String,
+    pub subject: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct SavedLabSlot {
+    pub lab: String,
+    pub slot: Slot,
+    /// The week of the cycle, left out for week A
+    #[serde(default, skip_serializing_if = "is_first")]
+    pub week: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct SavedPlacement {
+    pub class: SavedClass,
+    pub lab: String,
+    pub slot: Slot,
+    /// The week of the cycle, left out for week A
+    #[serde(default, skip_serializing_if = "is_first")]
+    pub week: usize,
+}
+
+fn is_first(week: &usize) -> bool {
+    *week == 0
+}
+
+fn first_version() -> u32 {
+    1
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum SavedError {
+    Missing { class: SavedClass },
+    NoLabs { class: SavedClass },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum SavedWarning {
+    UndesiredLab {
+        class: SavedClass,
+        was: String,
+        got: String,
+    },
+    UndesiredTime {
+        class: SavedClass,
+        slot: Slot,
+    },
+    CapLimited {
+        class: SavedClass,
+        cap: SavedCap,
+        limit: usize,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum SavedCap {
+    TeacherPerWeek {
+        teacher: String,
+        #[serde(default, skip_serializing_if = "is_first")]
+        week: usize,
+    },
+    GradePerDay {
+        grade: String,
+        #[serde(default, skip_serializing_if = "is_first")]
+        week: usize,
+        day: Day,
+    },
+    LabPerDay {
+        lab: String,
+        #[serde(default, skip_serializing_if = "is_first")]
+        week: usize,
+        day: Day,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SavedMove {
+    pub class: SavedClass,
+    pub from: Vec<SavedLabSlot>,
+    pub to: Vec<SavedLabSlot>,
+}
+
+/// A solution that refers to everything by name, so it can be loaded against a school
+/// imported again later, where the indices may differ
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SavedSolution {
+    #[serde(default = "first_version")]
+    pub version: u32,
+    pub slotted: Vec<SavedPlacement>,
+    #[serde(default)]
+    pub errors: Vec<SavedError>,
+    #[serde(default)]
+    pub warnings: Vec<SavedWarning>,
+    #[serde(default)]
+    pub statistics: Statistics,
+    #[serde(default)]
+    pub cancelled: bool,
+    #[serde(default)]
+    pub moved: Vec<SavedMove>,
+}
+
+fn save_class(school: &School, class: usize) -> SavedClass {
+    let class: ClassRef = school.get(class);
+    SavedClass {
+        teacher: class.teacher.name.clone(),
+        grade: class.grade.name.clone(),
+        subject: class.subject.name.clone(),
+    }
+}
+
+fn save_lab(school: &School, lab: usize) -> String {
+    school.get::<&Laboratory>(lab).name.clone()
+}
+
+fn save_lab_slots(school: &School, slots: &[LabSlot]) -> Vec<SavedLabSlot> {
+    slots
+        .iter()
+        .map(|&LabSlot { lab, slot, week }| SavedLabSlot {
+            lab: save_lab(school, lab),
+            slot: school.get::<&Slot>(slot).clone(),
+            week,
+        })
+        .collect()
+}
+
+fn load_teacher(school: &School, teacher: &str) -> anyhow::Result<usize> {
+    school
+        .teachers
+        .find_key(&Teacher {
+            name: teacher.to_string(),
+        })
+        .ok_or_else(|| anyhow!("Teacher does not exist: {teacher}"))
+}
+
+fn load_grade(school: &School, grade: &str) -> anyhow::Result<usize> {
+    school
+        .grades
+        .find_key(&Grade {
+            name: grade.to_string(),
+        })
+        .ok_or_else(|| anyhow!("Grade does not exist: {grade}"))
+}
+
+fn load_class(school: &School, class: &SavedClass) -> anyhow::Result<usize> {
+    let teacher = load_teacher(school, &class.teacher)?;
+    let grade = load_grade(school, &class.grade)?;
+    let subject = school
+        .subjects
+        .find_key(&Subject {
+            name: class.subject.clone(),
+        })
+        .ok_or_else(|| anyhow!("Subject does not exist: {}", class.subject))?;
+    school
+        .classes
+        .find_key(&Class {
+            teacher,
+            grade,
+            subject,
+        })
+        .ok_or_else(|| {
+            anyhow!(
+                "Class does not exist: {}, {}, {}",
+                class.teacher,
+                class.subject,
+                class.grade
+            )
+        })
+}
+
+fn load_lab(school: &School, lab: &str) -> anyhow::Result<usize> {
+    school
+        .labs
+        .find_key(&Laboratory {
+            name: lab.to_string(),
+        })
+        .ok_or_else(|| anyhow!("Lab does not exist: {lab}"))
+}
+
+fn load_slot(school: &School, slot: &Slot) -> anyhow::Result<usize> {
+    school
+        .slots
+        .find_key(slot)
+        .ok_or_else(|| anyhow!("Slot does not exist: {slot}"))
+}
+
+fn load_lab_slots(school: &School, slots: &[SavedLabSlot]) -> anyhow::Result<Vec<LabSlot>> {
+    slots
+        .iter()
+        .map(|s| {
+            Ok(LabSlot {
+                lab: load_lab(school, &s.lab)?,
+                slot: load_slot(school, &s.slot)?,
+                week: s.week,
+            })
+        })
+        .collect()
+}
+
+impl SavedSolution {
+    pub fn new(school: &School, solution: &Solution) -> Self {
+        let mut slotted: Vec<_> = solution
+            .slotted
+            .iter()
+            .map(|s| SavedPlacement {
+                class: save_class(school, s.class),
+                lab: save_lab(school, s.lab),
+                slot: school.get::<&Slot>(s.slot).clone(),
+                week: s.week,
+            })
+            .collect();
+        // Sort by name so saving the same schedule twice gives the same file
+        slotted.sort();
+        let errors = solution
+            .errors
+            .iter()
+            .map(|e| match *e {
+                Error::Missing(class) => SavedError::Missing {
+                    class: save_class(school, class),
+                },
+                Error::NoLabs(class) => SavedError::NoLabs {
+                    class: save_class(school, class),
+                },
+            })
+            .collect();
+        let warnings = solution
+            .warnings
+            .iter()
+            .map(|w| match *w {
+                Warning::UndesiredLab { class, was, got } => SavedWarning::UndesiredLab {
+                    class: save_class(school, class),
+                    was: save_lab(school, was),
+                    got: save_lab(school, got),
+                },
+                Warning::UndesiredTime { class, slot } => SavedWarning::UndesiredTime {
+                    class: save_class(school, class),
+                    slot: school.get::<&Slot>(slot).clone(),
+                },
+                Warning::CapLimited { class, cap, limit } => SavedWarning::CapLimited {
+                    class: save_class(school, class),
+                    cap: match cap {
+                        Cap::TeacherPerWeek(teacher, week) => SavedCap::TeacherPerWeek {
+                            teacher: school.get::<&Teacher>(teacher).name.clone(),
+                            week,
+                        },
+                        Cap::GradePerDay(grade, week, day) => SavedCap::GradePerDay {
+                            grade: school.get::<&Grade>(grade).name.clone(),
+                            week,
+                            day,
+                        },
+                        Cap::LabPerDay(lab, week, day) => SavedCap::LabPerDay {
+                            lab: save_lab(school, lab),
+                            week,
+                            day,
+                        },
+                    },
+                    limit,
+                },
+            })
+            .collect();
+        let moved = solution
+            .moved
+            .iter()
+            .map(|m| SavedMove {
+                class: save_class(school, m.class),
+                from: save_lab_slots(school, &m.from),
+                to: save_lab_slots(school, &m.to),
+            })
+            .collect();
+        Self {
+            version: VERSION,
+            slotted,
+            errors,
+            warnings,
+            statistics: solution.statistics.clone(),
+            cancelled: solution.cancelled,
+            moved,
+        }
+    }
+
+    /// Find everything the solution refers to in the school, skipping the entries with a name
+    /// that is not there
+    pub fn resolve(&self, school: &School) -> anyhow::Result<LoadedSolution> {
+        if self.version > VERSION {
+            bail!(
+                "The saved schedule is from a newer version ({}) than this program supports ({VERSION})",
+                self.version
+            );
+        }
+        let mut skipped = vec![];
+        let slotted = (self.slotted.iter())
+            .filter_map(|s| {
+                let loaded = (|| {
+                    Ok(LabSlottedClass {
+                        lab: load_lab(school, &s.lab)?,
+                        slot: load_slot(school, &s.slot)?,
+                        week: s.week,
+                        class: load_class(school, &s.class)?,
+                    })
+                })();
+                let what = format!("{} in {} at {}", s.class, s.lab, s.slot);
+                keep(&mut skipped, what, loaded)
+            })
+            .collect();
+        let errors = (self.errors.iter())
+            .filter_map(|e| {
+                let (SavedError::Missing { class } | SavedError::NoLabs { class }) = e;
+                let loaded = load_class(school, class).map(|class| match e {
+                    SavedError::Missing { .. } => Error::Missing(class),
+                    SavedError::NoLabs { .. } => Error::NoLabs(class),
+                });
+                keep(&mut skipped, format!("Error for {class}"), loaded)
+            })
+            .collect();
+        let warnings = (self.warnings.iter())
+            .filter_map(|w| {
+                let loaded = (|| {
+                    Ok(match w {
+                        SavedWarning::UndesiredLab { class, was, got } => Warning::UndesiredLab {
+                            class: load_class(school, class)?,
+                            was: load_lab(school, was)?,
+                            got: load_lab(school, got)?,
+                        },
+                        SavedWarning::UndesiredTime { class, slot } => Warning::UndesiredTime {
+                            class: load_class(school, class)?,
+                            slot: load_slot(school, slot)?,
+                        },
+                        SavedWarning::CapLimited { class, cap, limit } => Warning::CapLimited {
+                            class: load_class(school, class)?,
+                            cap: match cap {
+                                SavedCap::TeacherPerWeek { teacher, week } => {
+                                    Cap::TeacherPerWeek(load_teacher(school, teacher)?, *week)
+                                }
+                                SavedCap::GradePerDay { grade, week, day } => {
+                                    Cap::GradePerDay(load_grade(school, grade)?, *week, *day)
+                                }
+                                SavedCap::LabPerDay { lab, week, day } => {
+                                    Cap::LabPerDay(load_lab(school, lab)?, *week, *day)
+                                }
+                            },
+                            limit: *limit,
+                        },
+                    })
+                })();
+                let (SavedWarning::UndesiredLab { class, .. }
+                | SavedWarning::UndesiredTime { class, .. }
+                | SavedWarning::CapLimited { class, .. }) = w;
+                keep(&mut skipped, format!("Warning for {class}"), loaded)
+            })
+            .collect();
+        let moved = (self.moved.iter())
+            .filter_map(|m| {
+                let loaded = (|| {
+                    Ok(Move {
+                        class: load_class(school, &m.class)?,
+                        from: load_lab_slots(school, &m.from)?,
+                        to: load_lab_slots(school, &m.to)?,
+                    })
+                })();
+                keep(&mut skipped, format!("Move of {}", m.class), loaded)
+            })
+            .collect();
+        Ok(LoadedSolution {
+            solution: Solution {
+                slotted,
+                errors,
+                warnings,
+                statistics: self.statistics.clone(),
+                cancelled: self.cancelled,
+                moved,
+            },
+            skipped,
+        })
+    }
+}
+
+/// The loaded entry, or `None` after noting why it was skipped
+fn keep<T>(skipped: &mut Vec<String>, what: impl Display, loaded: anyhow::Result<T>) -> Option<T> {
+    match loaded {
+        Ok(loaded) => Some(loaded),
+        Err(e) => {
+            skipped.push(format!("{what}: {e}"));
+            None
+        }
+    }
+}
+
+impl Display for SavedClass {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}, {}, {}", self.teacher, self.subject, self.grade)
+    }
+}
+
+/// A saved solution loaded against a school, with what could not be found in it
+pub struct LoadedSolution {
+    pub solution: Solution,
+    /// Each entry that was left out because of a name that is not in the school, and why
+    pub skipped: Vec<String>,
+}
+
+/// Read a saved solution, leaving out the entries with names that are not in the school
+pub fn read_solution(school: &School, p: impl AsRef<Path>) -> anyhow::Result<LoadedSolution> {
+    let file = File::open(p)?;
+    let reader = BufReader::new(file);
+    let saved: SavedSolution = serde_json::from_reader(reader)?;
+    saved.resolve(school)
+}
+
+pub fn save_solution(
+    school: &School,
+    solution: &Solution,
+    p: impl AsRef<Path>,
+) -> anyhow::Result<()> {
+    let s = serde_json::to_string_pretty(&SavedSolution::new(school, solution))?;
+    fs::write(p, s)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{school::Day::Monday, testing};
+
+    #[test]
+    fn skips_unknown_names() {
+        let before = testing::school(
+            &[
+                ("1A", "Química", "Ana", Monday, "7:30"),
+                ("1B", "Física", "Bruno", Monday, "7:30"),
+            ],
+            &["L1", "L2"],
+        );
+        let after = testing::school(
+            &[
+                ("1A", "Química", "Ana", Monday, "7:30"),
+                ("1B", "Física", "Bruna", Monday, "7:30"),
+            ],
+            &["L1", "L2"],
+        );
+        let slot = before.slots_of(0).next().unwrap();
+        let slotted = (0..2)
+            .map(|class| LabSlottedClass {
+                lab: class,
+                slot,
+                week: 0,
+                class,
+            })
+            .collect();
+        let solution = Solution {
+            slotted,
+            errors: vec![Error::Missing(1)],
+            warnings: vec![],
+            statistics: Statistics::default(),
+            cancelled: false,
+            moved: vec![],
+        };
+        let saved = SavedSolution::new(&before, &solution);
+        assert_eq!(saved.version, VERSION);
+        let loaded = saved.resolve(&after).unwrap();
+        assert_eq!(loaded.solution.slotted.len(), 1);
+        assert!(loaded.solution.errors.is_empty());
+        assert_eq!(loaded.skipped.len(), 2);
+    }
+
+    #[test]
+    fn loads_a_hand_written_schedule() {
+        let school = testing::school(&[("1A", "Química", "Ana", Monday, "7:30")], &["L1"]);
+        let saved: SavedSolution = serde_json::from_value(serde_json::json!({
+            "slotted": [{
+                "class": {"teacher": "Ana", "grade": "1A", "subject": "Química"},
+                "lab": "L1",
+                "slot": {"day": "Monday", "time": "07:30"},
+            }],
+        }))
+        .unwrap();
+        let loaded = saved.resolve(&school).unwrap();
+        assert_eq!(loaded.solution.slotted.len(), 1);
+        assert!(loaded.skipped.is_empty());
+    }
+}
@@ -1,24 +1,145 @@
+use crate::school::Day;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Display,
+    time::Duration,
+};
+
+#[derive(Debug, Clone)]
 pub struct LabSlottedClass {
     pub lab: usize,
     pub slot: usize,
+    /// The week of the cycle, 0 being week A
+    pub week: usize,
     pub class: usize,
 }
 
+#[derive(Debug, Clone)]
 pub enum Error {
     Missing(usize),
     NoLabs(usize),
 }
 
+#[derive(Debug, Clone)]
 pub enum Warning {
     UndesiredLab {
         class: usize,
         was: usize,
         got: usize,
     },
+    /// The class is at a time its teacher would rather not have
+    UndesiredTime { class: usize, slot: usize },
+    /// The class could have been placed better, or at all, if the cap had not been reached
+    CapLimited {
+        class: usize,
+        cap: Cap,
+        limit: usize,
+    },
+}
+
+/// What a cap on lab sessions counts
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Cap {
+    /// A teacher in a week of the cycle
+    TeacherPerWeek(usize, usize),
+    /// A grade on a day of a week of the cycle
+    GradePerDay(usize, usize, Day),
+    /// A lab on a day of a week of the cycle
+    LabPerDay(usize, usize, Day),
+}
+
+/// The letter a week of the cycle goes by, A being the first
+pub fn week_name(week: usize) -> char {
+    char::from_u32('A' as u32 + week as u32).unwrap_or('?')
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct LabSlot {
+    pub lab: usize,
+    pub slot: usize,
+    pub week: usize,
+}
+
+/// A class that was placed differently than in a previous solution
+#[derive(Debug, Clone)]
+pub struct Move {
+    pub class: usize,
+    /// Where the class was before, empty if it was not placed
+    pub from: Vec<LabSlot>,
+    /// Where the class is now, empty if it is no longer placed
+    pub to: Vec<LabSlot>,
+}
+
+/// What the solver went through to reach a solution
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Statistics {
+    /// States visited by the search, or calls to the SAT solver
+    pub nodes: u64,
+    /// States pruned without leading to a solution, or conflicts found by the SAT solver
+    pub backtracks: u64,
+    /// How many lab choices were considered at most (1 means only the first choice)
+    pub relaxations: usize,
+    /// Classes dropped because no solution could be found with them
+    pub dropped: usize,
+    /// Times the improvement phase found a better schedule
+    pub improvements: u64,
+    pub elapsed: Duration,
 }
 
 pub struct Solution {
     pub slotted: Vec<LabSlottedClass>,
     pub errors: Vec<Error>,
     pub warnings: Vec<Warning>,
+    pub statistics: Statistics,
+    /// The solve was cancelled before finishing, so not every class was considered
+    pub cancelled: bool,
+    /// Assignments that changed from the previous solution, when solving against one
+    pub moved: Vec<Move>,
+}
+
+impl Solution {
+    fn placements(&self) -> BTreeMap<usize, Vec<LabSlot>> {
+        let mut placements: BTreeMap<usize, Vec<LabSlot>> = BTreeMap::new();
+        for slotted in &self.slotted {
+            placements.entry(slotted.class).or_default().push(LabSlot {
+                lab: slotted.lab,
+                slot: slotted.slot,
+                week: slotted.week,
+            });
+        }
+        for slots in placements.values_mut() {
+            slots.sort();
+        }
+        placements
+    }
+
+    /// Every class placed differently than in `previous`
+    pub fn moves_from(&self, previous: &Solution) -> Vec<Move> {
+        let before = previous.placements();
+        let after = self.placements();
+        let classes: BTreeSet<usize> = before.keys().chain(after.keys()).copied().collect();
+        classes
+            .into_iter()
+            .filter_map(|class| {
+                let from = before.get(&class).cloned().unwrap_or_default();
+                let to = after.get(&class).cloned().unwrap_or_default();
+                (from != to).then_some(Move { class, from, to })
+            })
+            .collect()
+    }
+}
+
+impl Display for Statistics {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} nodes, {} backtracks, {} lab choices, {} dropped, ",
+            self.nodes, self.backtracks, self.relaxations, self.dropped,
+        )?;
+        if self.improvements > 0 {
+            write!(f, "{} improvements, ", self.improvements)?;
+        }
+        write!(f, "{:.1}s", self.elapsed.as_secs_f32())
+    }
 }
@@ -0,0 +1,510 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use super::solution::{week_name, Cap, Solution};
+use crate::{
+    rules::{Rules, ANY},
+    school::{ClassRef, Grade, Laboratory, School, Slot, Teacher},
+};
+
+/// A rule broken by a solution
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Violation {
+    /// More classes than the lab takes at once in the same slot of a week
+    DoubleBooked {
+        lab: usize,
+        slot: usize,
+        week: usize,
+        classes: Vec<usize>,
+    },
+    /// The class does not meet in the slot it was placed in
+    WrongSlot { class: usize, slot: usize },
+    /// The class is not in the rules, so it should not be in a lab
+    NotInRules { class: usize },
+    /// The lab is not one of the lab choices of the class
+    LabNotAllowed { class: usize, lab: usize },
+    /// The lab can't be used at the time of the slot
+    ForbiddenTime {
+        class: usize,
+        lab: usize,
+        slot: usize,
+    },
+    /// The teacher of the class can't have lab classes at the time of the slot
+    TeacherUnavailable { class: usize, slot: usize },
+    /// The class is not in a single lab for the periods of one of its sessions, or in two when
+    /// split, or not in the weeks of the cycle it meets in
+    BrokenSession { class: usize },
+    /// More sessions than a cap allows
+    CapExceeded { cap: Cap, limit: usize, used: usize },
+    /// More students than the lab seats in the same slot of a week
+    Overfull {
+        lab: usize,
+        slot: usize,
+        week: usize,
+        students: usize,
+        seats: usize,
+    },
+}
+
+impl Violation {
+    pub fn describe(&self, school: &School) -> String {
+        let lab = |lab: usize| school.get::<&Laboratory>(lab).to_string();
+        let slot = |slot: usize| school.get::<&Slot>(slot).to_string();
+        let class = |class: usize| school.get::<ClassRef>(class).to_string();
+        match self {
+            Violation::DoubleBooked {
+                lab: l,
+                slot: s,
+                week,
+                classes,
+            } => {
+                let classes: Vec<_> = classes.iter().map(|&c| class(c)).collect();
+                format!(
+                    "{} at {} of week {} has more classes than it takes: {}",
+                    lab(*l),
+                    slot(*s),
+                    week_name(*week),
+                    classes.join(", ")
+                )
+            }
+            Violation::WrongSlot { class: c, slot: s } => {
+                format!("{} does not meet at {}", class(*c), slot(*s))
+            }
+            Violation::NotInRules { class: c } => format!("{} is not in the rules", class(*c)),
+            Violation::LabNotAllowed { class: c, lab: l } => {
+                format!("{} is not a lab choice of {}", lab(*l), class(*c))
+            }
+            Violation::ForbiddenTime {
+                class: c,
+                lab: l,
+                slot: s,
+            } => format!(
+                "{} can't be used at {}, where {} was placed",
+                lab(*l),
+                slot(*s),
+                class(*c)
+            ),
+            Violation::TeacherUnavailable { class: c, slot: s } => {
+                let teacher = &school.get::<ClassRef>(*c).teacher.name;
+                format!(
+                    "{teacher} is unavailable at {}, where {} was placed",
+                    slot(*s),
+                    class(*c)
+                )
+            }
+            Violation::BrokenSession { class: c } => {
+                format!(
+                    "{} is not in its labs for all periods of a session in the weeks it meets",
+                    class(*c)
+                )
+            }
+            Violation::CapExceeded { cap, limit, used } => {
+                let cap = match *cap {
+                    Cap::TeacherPerWeek(t, week) => {
+                        format!("{} in week {}", school.get::<&Teacher>(t), week_name(week))
+                    }
+                    Cap::GradePerDay(g, week, day) => {
+                        let grade = school.get::<&Grade>(g);
+                        format!("{grade} on {day:?} of week {}", week_name(week))
+                    }
+                    Cap::LabPerDay(l, week, day) => {
+                        format!("{} on {day:?} of week {}", lab(l), week_name(week))
+                    }
+                };
+                format!("{cap} has {used} sessions, more than the cap of {limit}")
+            }
+            Violation::Overfull {
+                lab: l,
+                slot: s,
+                week,
+                students,
+                seats,
+            } => format!(
+                "{} at {} of week {} has {students} students, more than its {seats} seats",
+                lab(*l),
+                slot(*s),
+                week_name(*week)
+            ),
+        }
+    }
+}
+
+/// What the rules say about a class
+struct ClassRule {
+    /// Lab choices
+    labs: BTreeSet<usize>,
+    double: bool,
+    every: usize,
+    /// The teacher of the second half-group when split, the class's own when not named
+    split: Option<usize>,
+}
+
+/// The class of each rule, worked out from the names in the rules and the school. A class named
+/// by more than one rule follows the first.
+fn class_rules(school: &School, rules: &Rules) -> BTreeMap<usize, ClassRule> {
+    let aliases = &rules.aliases;
+    let teacher_id = |name: &str| {
+        let name = aliases.teacher(name);
+        (school.teachers.iter()).find_map(|(&id, t)| (t.name == name).then_some(id))
+    };
+    let lab_id = |name: &str| {
+        let name = aliases.lab(name);
+        (school.labs.iter()).find_map(|(&id, l)| (l.name == name).then_some(id))
+    };
+    let mut found = BTreeMap::new();
+    for rule in rules.expand(school) {
+        let (teacher, grade, subject) = (
+            aliases.teacher(rule.teacher),
+            aliases.grade(rule.grade),
+            aliases.subject(rule.subject),
+        );
+        let Some((&class, own)) = school.classes.iter().find(|(_, class)| {
+            school.teachers.get(class.teacher).unwrap().name == teacher
+                && school.grades.get(class.grade).unwrap().name == grade
+                && school.subjects.get(class.subject).unwrap().name == subject
+        }) else {
+            continue;
+        };
+        let split = rule.split.map(|split| match &split.teacher {
+            Some(name) => teacher_id(name).unwrap_or(own.teacher),
+            None => own.teacher,
+        });
+        found.entry(class).or_insert(ClassRule {
+            labs: rule.labs.iter().filter_map(|lab| lab_id(lab)).collect(),
+            double: rule.double,
+            every: rule.every.max(1),
+            split,
+        });
+    }
+    found
+}
+
+/// The value for a name
+fn entry<'a, T>(
+    values: &'a BTreeMap<String, T>,
+    name: &str,
+    canonical: impl Fn(&str) -> String,
+) -> Option<&'a T> {
+    (values.iter())
+        .find(|(n, _)| canonical(n) == name)
+        .map(|(_, value)| value)
+}
+
+/// The limit for a name, or the one for every name
+fn limit(
+    limits: &BTreeMap<String, usize>,
+    name: &str,
+    canonical: impl Fn(&str) -> String,
+) -> Option<usize> {
+    let named = (limits.iter()).filter(|(n, _)| *n != ANY);
+    (named.map(|(n, &max)| (canonical(n), max)))
+        .find(|(n, _)| n == name)
+        .map(|(_, max)| max)
+        .or_else(|| limits.get(ANY).copied())
+}
+
+/// Whether the lab slots are one session of the class: one slot, or two adjacent ones when
+/// double, in one lab, or two when split, in every week of the cycle it meets in
+fn is_session(
+    school: &School,
+    rule: &ClassRule,
+    weeks: usize,
+    placement: &BTreeSet<(usize, usize, usize)>,
+) -> bool {
+    let labs: BTreeSet<usize> = placement.iter().map(|&(lab, _, _)| lab).collect();
+    let slots: BTreeSet<usize> = placement.iter().map(|&(_, slot, _)| slot).collect();
+    let used: BTreeSet<usize> = placement.iter().map(|&(_, _, week)| week).collect();
+    let whole = labs.len() * slots.len() * used.len() == placement.len();
+    let labs_ok = labs.len() == if rule.split.is_some() { 2 } else { 1 };
+    let slots: Vec<usize> = slots.into_iter().collect();
+    let slots_ok = match slots[..] {
+        [_] => !rule.double,
+        [a, b] => rule.double && (school.next_slot(a) == Some(b) || school.next_slot(b) == Some(a)),
+        _ => false,
+    };
+    let first = *used.first().unwrap();
+    let expected: BTreeSet<usize> = (first..weeks).step_by(rule.every).collect();
+    let weeks_ok = first < rule.every.min(weeks) && used == expected;
+    whole && labs_ok && slots_ok && weeks_ok
+}
+
+/// Check a solution from scratch against the school and the rules, without trusting the solver
+/// that produced it: everything is worked out again from the rules, and nothing the solver
+/// uses is called
+pub fn verify(school: &School, rules: &Rules, solution: &Solution) -> Vec<Violation> {
+    let aliases = &rules.aliases;
+    let class_rules = class_rules(school, rules);
+    let weeks = rules.weeks.max(1);
+    let mut violations = vec![];
+    let lab_name = |lab: usize| &school.get::<&Laboratory>(lab).name;
+    let forbidden = |lab: usize, slot: &Slot| {
+        (rules.forbidden_times.iter())
+            .any(|(name, times)| aliases.lab(name) == *lab_name(lab) && times.contains(&slot.time))
+    };
+    let unavailable = |teacher: usize, slot: &Slot| {
+        let name = &school.get::<&Teacher>(teacher).name;
+        (rules.teachers.iter())
+            .filter(|t| aliases.teacher(&t.name) == *name)
+            .any(|t| t.unavailable.iter().any(|p| p.contains(slot)))
+    };
+    let mut occupied: BTreeMap<(usize, usize, usize), Vec<usize>> = BTreeMap::new();
+    let mut placements: BTreeMap<usize, BTreeSet<(usize, usize, usize)>> = BTreeMap::new();
+    for slotted in &solution.slotted {
+        let (class, lab, slot, week) = (slotted.class, slotted.lab, slotted.slot, slotted.week);
+        occupied.entry((lab, slot, week)).or_default().push(class);
+        placements
+            .entry(class)
+            .or_default()
+            .insert((lab, slot, week));
+        if !school.slots_of(class).any(|s| s == slot) {
+            violations.push(Violation::WrongSlot { class, slot });
+        }
+        let rule = class_rules.get(&class);
+        match rule {
+            None => violations.push(Violation::NotInRules { class }),
+            Some(rule) if !rule.labs.contains(&lab) => {
+                violations.push(Violation::LabNotAllowed { class, lab })
+            }
+            Some(_) => {}
+        }
+        let at: &Slot = school.get(slot);
+        if forbidden(lab, at) {
+            violations.push(Violation::ForbiddenTime { class, lab, slot });
+        }
+        let teacher = school.classes.get(class).unwrap().teacher;
+        let second = rule.and_then(|r| r.split).filter(|&t| t != teacher);
+        let second_busy = second.is_some_and(|t| unavailable(t, at) || school.teaches_at(t, slot));
+        if unavailable(teacher, at) || second_busy {
+            violations.push(Violation::TeacherUnavailable { class, slot });
+        }
+    }
+    let mut used: BTreeMap<Cap, usize> = BTreeMap::new();
+    for (&class, placement) in &placements {
+        let rule = class_rules.get(&class);
+        if rule.is_some_and(|rule| !is_session(school, rule, weeks, placement)) {
+            violations.push(Violation::BrokenSession { class });
+        }
+        // A session counts once towards each cap, however many periods and labs it takes
+        let own = school.classes.get(class).unwrap();
+        let mut teachers = vec![own.teacher];
+        teachers.extend(rule.and_then(|r| r.split).filter(|&t| t != own.teacher));
+        let mut counted = BTreeSet::new();
+        for &(lab, slot, week) in placement {
+            let day = school.get::<&Slot>(slot).day;
+            counted.extend(teachers.iter().map(|&t| Cap::TeacherPerWeek(t, week)));
+            counted.insert(Cap::GradePerDay(own.grade, week, day));
+            counted.insert(Cap::LabPerDay(lab, week, day));
+        }
+        for cap in counted {
+            *used.entry(cap).or_default() += 1;
+        }
+    }
+    let caps = &rules.caps;
+    for (cap, used) in used {
+        let limit = match cap {
+            Cap::TeacherPerWeek(t, _) => limit(
+                &caps.teacher_per_week,
+                &school.get::<&Teacher>(t).name,
+                |n| aliases.teacher(n),
+            ),
+            Cap::GradePerDay(g, _, _) => {
+                limit(&caps.grade_per_day, &school.get::<&Grade>(g).name, |n| {
+                    aliases.grade(n)
+                })
+            }
+            Cap::LabPerDay(l, _, _) => limit(&caps.lab_per_day, lab_name(l), |n| aliases.lab(n)),
+        };
+        if let Some(limit) = limit.filter(|&limit| used > limit) {
+            violations.push(Violation::CapExceeded { cap, limit, used });
+        }
+    }
+    for ((lab, slot, week), classes) in occupied {
+        let capacity = entry(&rules.labs, lab_name(lab), |n| aliases.lab(n));
+        let concurrent = capacity.map_or(1, |c| c.classes);
+        let students = (classes.iter())
+            .filter_map(|&class| {
+                let grade = school.classes.get(class).unwrap().grade;
+                let size = entry(&rules.sizes, &school.get::<&Grade>(grade).name, |n| {
+                    aliases.grade(n)
+                })?;
+                let split = class_rules.get(&class).is_some_and(|r| r.split.is_some());
+                Some(if split { size.div_ceil(2) } else { *size })
+            })
+            .sum();
+        if classes.len() > concurrent {
+            violations.push(Violation::DoubleBooked {
+                lab,
+                slot,
+                week,
+                classes,
+            });
+        } else if let Some(seats) = capacity.and_then(|c| c.seats) {
+            if students > seats {
+                violations.push(Violation::Overfull {
+                    lab,
+                    slot,
+                    week,
+                    students,
+                    seats,
+                });
+            }
+        }
+    }
+    violations
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::{
+        school::Day::Monday,
+        solver::solution::{LabSlottedClass, Statistics},
+        testing,
+    };
+
+    fn solution(slotted: Vec<LabSlottedClass>) -> Solution {
+        Solution {
+            slotted,
+            errors: vec![],
+            warnings: vec![],
+            statistics: Statistics::default(),
+            cancelled: false,
+            moved: vec![],
+        }
+    }
+
+    #[test]
+    fn detects_double_booking() {
+        let school = testing::school(
+            &[
+                ("1A", "Química", "Ana", Monday, "7:30"),
+                ("1B", "Física", "Bruno", Monday, "7:30"),
+            ],
+            &["L1", "L2"],
+        );
+        let rules = testing::rules(json!({
+            "classes": [
+                {"subject": "Química", "teachers": [
+                    {"name": "Ana", "grades": [{"name": "1A", "labs": ["L1"]}]},
+                ]},
+                {"subject": "Física", "teachers": [
+                    {"name": "Bruno", "grades": [{"name": "1B", "labs": ["L1", "L2"]}]},
+                ]},
+            ],
+        }));
+        let chemistry = testing::class(&school, "1A", "Química", "Ana");
+        let physics = testing::class(&school, "1B", "Física", "Bruno");
+        let (l1, l2) = (testing::lab(&school, "L1"), testing::lab(&school, "L2"));
+        let slot = school.slots_of(chemistry).next().unwrap();
+        let at = |class, lab| LabSlottedClass {
+            lab,
+            slot,
+            week: 0,
+            class,
+        };
+
+        let booked = solution(vec![at(chemistry, l1), at(physics, l1)]);
+        assert_eq!(
+            verify(&school, &rules, &booked),
+            [Violation::DoubleBooked {
+                lab: l1,
+                slot,
+                week: 0,
+                classes: vec![chemistry, physics],
+            }]
+        );
+
+        let apart = solution(vec![at(chemistry, l1), at(physics, l2)]);
+        assert_eq!(verify(&school, &rules, &apart), []);
+
+        // A lab that takes two classes at once is not double-booked by two
+        let shared = testing::rules(json!({
+            "classes": rules.classes,
+            "labs": {"L1": {"classes": 2}},
+        }));
+        assert_eq!(verify(&school, &shared, &booked), []);
+    }
+
+    #[test]
+    fn detects_wrong_slots_labs_and_times() {
+        let school = testing::school(
+            &[
+                ("1A", "Química", "Ana", Monday, "7:30"),
+                ("1B", "Física", "Bruno", Monday, "8:20"),
+            ],
+            &["L1", "L2"],
+        );
+        let rules = testing::rules(json!({
+            "classes": [
+                {"subject": "Química", "teachers": [
+                    {"name": "Ana", "grades": [{"name": "1A", "labs": ["L1"]}]},
+                ]},
+                {"subject": "Física", "teachers": [
+                    {"name": "Bruno", "grades": [{"name": "1B", "labs": ["L1", "L2"]}]},
+                ]},
+            ],
+            "forbidden_times": {"L2": ["08:20"]},
+        }));
+        let chemistry = testing::class(&school, "1A", "Química", "Ana");
+        let physics = testing::class(&school, "1B", "Física", "Bruno");
+        let (l1, l2) = (testing::lab(&school, "L1"), testing::lab(&school, "L2"));
+        let first = school.slots_of(chemistry).next().unwrap();
+        let second = school.slots_of(physics).next().unwrap();
+        let at = |class, lab, slot| LabSlottedClass {
+            lab,
+            slot,
+            week: 0,
+            class,
+        };
+
+        let wrong_slot = solution(vec![at(chemistry, l1, second)]);
+        assert_eq!(
+            verify(&school, &rules, &wrong_slot),
+            [Violation::WrongSlot {
+                class: chemistry,
+                slot: second,
+            }]
+        );
+
+        let not_allowed = solution(vec![at(chemistry, l2, first)]);
+        assert_eq!(
+            verify(&school, &rules, &not_allowed),
+            [Violation::LabNotAllowed {
+                class: chemistry,
+                lab: l2,
+            }]
+        );
+
+        let forbidden = solution(vec![at(physics, l2, second)]);
+        assert_eq!(
+            verify(&school, &rules, &forbidden),
+            [Violation::ForbiddenTime {
+                class: physics,
+                lab: l2,
+                slot: second,
+            }]
+        );
+    }
+
+    #[test]
+    fn accepts_a_split_class_in_two_labs() {
+        let school = testing::school(&[("1A", "Química", "Ana", Monday, "7:30")], &["L1", "L2"]);
+        let rules = testing::rules(json!({
+            "classes": [{"subject": "Química", "teachers": [
+                {"name": "Ana", "grades": [{"name": "1A", "labs": ["L1", "L2"], "split": {}}]},
+            ]}],
+        }));
+        let class = testing::class(&school, "1A", "Química", "Ana");
+        let slot = school.slots_of(class).next().unwrap();
+        let halves = (["L1", "L2"].iter())
+            .map(|lab| LabSlottedClass {
+                lab: testing::lab(&school, lab),
+                slot,
+                week: 0,
+                class,
+            })
+            .collect();
+        assert_eq!(verify(&school, &rules, &solution(halves)), []);
+    }
+}
@@ -8,13 +8,17 @@ use encoding_rs::WINDOWS_1252;
 use scraper::{Html, Selector};
 
 use crate::{
+    rules::Aliases,
     school::{Class, Grade, Laboratory, School, Slot, SlottedClass, Subject, Teacher, DAYS},
     table::Table,
 };
 
+/// Read the timetable exported by the vendor and the list of labs, replacing alternate
+/// spellings of names with the ones in `aliases`
 pub fn load_school(
     school_path: impl AsRef<Path>,
     labs_path: impl AsRef<Path>,
+    aliases: &Aliases,
 ) -> anyhow::Result<School> {
     let mut teachers = Table::new();
     let mut grades = Table::new();
@@ -45,7 +49,9 @@ pub fn load_school(
             .strip_prefix("Turma ")
             .map(|n| n.to_string())
             .unwrap_or(name);
-        let grade_id = grades.insert_unique(Grade { name });
+        let grade_id = grades.insert_unique(Grade {
+            name: aliases.grade(&name),
+        });
         for mut row in table.skip(1) {
             let time = row
                 .next()
@@ -56,32 +62,31 @@ pub fn load_school(
                 .unwrap();
             for (mut cell, &day) in row.zip(DAYS.iter()) {
                 let slot_id = slots.insert_unique(Slot { day, time });
-                match (cell.next(), cell.next()) {
-                    (Some(subject), Some(teacher)) => {
-                        let subject_id = subjects.insert_unique(Subject {
-                            name: subject.replace('\u{00A0}', " "),
-                        });
-                        let teacher_id = teachers.insert_unique(Teacher {
-                            name: teacher.replace('\u{00A0}', " "),
-                        });
-                        let class_id = classes.insert_unique(Class {
-                            teacher: teacher_id,
-                            grade: grade_id,
-                            subject: subject_id,
-                        });
-                        slotted_classes.insert_unique(SlottedClass {
-                            slot: slot_id,
-                            class: class_id,
-                        });
-                    }
-                    _ => {}
+                if let (Some(subject), Some(teacher)) = (cell.next(), cell.next()) {
+                    let subject_id = subjects.insert_unique(Subject {
+                        name: aliases.subject(&subject.replace('\u{00A0}', " ")),
+                    });
+                    let teacher_id = teachers.insert_unique(Teacher {
+                        name: aliases.teacher(&teacher.replace('\u{00A0}', " ")),
+                    });
+                    let class_id = classes.insert_unique(Class {
+                        teacher: teacher_id,
+                        grade: grade_id,
+                        subject: subject_id,
+                    });
+                    slotted_classes.insert_unique(SlottedClass {
+                        slot: slot_id,
+                        class: class_id,
+                    });
                 }
             }
         }
     }
     let lab_names: Vec<String> = serde_json::from_reader(BufReader::new(File::open(labs_path)?))?;
     for name in lab_names {
-        labs.insert_unique(Laboratory { name });
+        labs.insert_unique(Laboratory {
+            name: aliases.lab(&name),
+        });
     }
     Ok(School {
         teachers,
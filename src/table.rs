@@ -33,10 +33,7 @@ impl<T> Table<T> {
     where
         T: PartialEq,
     {
-        match self.iter().find(|(_, r)| r == &value) {
-            Some((i, _)) => Some(*i),
-            None => None,
-        }
+        self.iter().find(|(_, r)| r == &value).map(|(i, _)| *i)
     }
     pub fn insert_unique(&mut self, value: T) -> usize
     where
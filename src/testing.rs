@@ -0,0 +1,78 @@
+//! Small schools and rules to test with
+
+use serde_json::Value;
+
+use crate::{
+    rules::Rules,
+    school::{Class, Day, Grade, Laboratory, School, Slot, SlottedClass, Subject, Teacher},
+    table::Table,
+};
+
+/// A period of a class: its grade, subject, teacher, day and time
+pub type Period<'a> = (&'a str, &'a str, &'a str, Day, &'a str);
+
+/// A school with the classes meeting at the periods, and the labs
+pub fn school(periods: &[Period], labs: &[&str]) -> School {
+    let mut school = School {
+        slots: Table::new(),
+        teachers: Table::new(),
+        grades: Table::new(),
+        subjects: Table::new(),
+        classes: Table::new(),
+        slotted_classes: Table::new(),
+        labs: Table::new(),
+    };
+    for &(grade, subject, teacher, day, time) in periods {
+        let class = Class {
+            teacher: (school.teachers).insert_unique(Teacher {
+                name: teacher.into(),
+            }),
+            grade: (school.grades).insert_unique(Grade { name: grade.into() }),
+            subject: (school.subjects).insert_unique(Subject {
+                name: subject.into(),
+            }),
+        };
+        let slot = Slot {
+            day,
+            time: time.parse().unwrap(),
+        };
+        let slotted = SlottedClass {
+            slot: school.slots.insert_unique(slot),
+            class: school.classes.insert_unique(class),
+        };
+        school.slotted_classes.insert_unique(slotted);
+    }
+    for &lab in labs {
+        school.labs.insert_unique(Laboratory { name: lab.into() });
+    }
+    school
+}
+
+/// Rules read from JSON, without the fields every rules file must have
+pub fn rules(mut json: Value) -> Rules {
+    let fields = json.as_object_mut().unwrap();
+    fields.entry("classes").or_insert(Value::Array(vec![]));
+    fields
+        .entry("forbidden_times")
+        .or_insert(Value::Object(Default::default()));
+    serde_json::from_value(json).unwrap()
+}
+
+/// The id of the class of the grade, subject and teacher
+pub fn class(school: &School, grade: &str, subject: &str, teacher: &str) -> usize {
+    (school.classes.iter())
+        .find(|(_, class)| {
+            school.grades.get(class.grade).unwrap().name == grade
+                && school.subjects.get(class.subject).unwrap().name == subject
+                && school.teachers.get(class.teacher).unwrap().name == teacher
+        })
+        .map(|(&id, _)| id)
+        .unwrap()
+}
+
+/// The id of the lab
+pub fn lab(school: &School, name: &str) -> usize {
+    (school.labs)
+        .find_key(&Laboratory { name: name.into() })
+        .unwrap()
+}
@@ -0,0 +1,437 @@
+use std::{collections::BTreeMap, fmt::Display};
+
+use crate::{
+    rules::{Aliases, Groups, Pattern, Rules, ANY},
+    school::{Class, Grade, Laboratory, School, Subject, Teacher},
+};
+
+/// How many names are suggested for each unknown one
+const MAX_SUGGESTIONS: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Teacher,
+    Grade,
+    Subject,
+    Lab,
+    /// The teacher, grade and subject exist, but the teacher doesn't teach that subject to
+    /// that grade
+    Class,
+    /// A `@group` that is not defined in the rules
+    Group,
+}
+
+/// A name in the rules that is not in the school
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub kind: Kind,
+    pub name: String,
+    /// Where in the rules the name is, from the outermost part
+    pub location: Vec<String>,
+    /// Known names that look like the unknown one, closest first
+    pub suggestions: Vec<String>,
+}
+
+impl Display for Kind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let kind = match self {
+            Kind::Teacher => "Teacher",
+            Kind::Grade => "Grade",
+            Kind::Subject => "Subject",
+            Kind::Lab => "Lab",
+            Kind::Class => "Class",
+            Kind::Group => "Group",
+        };
+        write!(f, "{kind}")
+    }
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} does not exist: {}", self.kind, self.name)?;
+        if !self.location.is_empty() {
+            write!(f, " (in {})", self.location.join(" > "))?;
+        }
+        if !self.suggestions.is_empty() {
+            write!(f, ", did you mean {}?", self.suggestions.join(" or "))?;
+        }
+        Ok(())
+    }
+}
+
+/// A class meeting every few weeks when that doesn't divide the weeks the schedule takes to
+/// repeat, so it would meet at uneven intervals from one cycle to the next
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnevenWeeks {
+    /// Weeks between meetings of the class
+    pub every: usize,
+    /// Weeks the schedule takes to repeat
+    pub weeks: usize,
+    /// Where in the rules the class is, from the outermost part
+    pub location: Vec<String>,
+}
+
+impl UnevenWeeks {
+    /// The numbers of weeks between meetings that fit the cycle
+    pub fn fitting(&self) -> Vec<usize> {
+        (1..=self.weeks)
+            .filter(|&every| self.weeks.is_multiple_of(every))
+            .collect()
+    }
+}
+
+impl Display for UnevenWeeks {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let fitting: Vec<_> = self.fitting().iter().map(|e| e.to_string()).collect();
+        write!(
+            f,
+            "Meeting every {} weeks does not fit a cycle of {} weeks (in {}), use one of {}",
+            self.every,
+            self.weeks,
+            self.location.join(" > "),
+            fitting.join(", ")
+        )
+    }
+}
+
+/// Lowercase, without accents and with every kind of space as a plain one, so names typed
+/// differently compare the same
+pub fn fold(name: &str) -> String {
+    name.chars()
+        .flat_map(char::to_lowercase)
+        .map(|c| match c {
+            'á' | 'à' | 'â' | 'ã' | 'ä' | 'ª' => 'a',
+            'é' | 'è' | 'ê' | 'ë' => 'e',
+            'í' | 'ì' | 'î' | 'ï' => 'i',
+            'ó' | 'ò' | 'ô' | 'õ' | 'ö' | 'º' => 'o',
+            'ú' | 'ù' | 'û' | 'ü' => 'u',
+            'ç' => 'c',
+            'ñ' => 'n',
+            c if c.is_whitespace() => ' ',
+            c => c,
+        })
+        .collect::<String>()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// The known names closest to `name`, ignoring accents and case
+pub fn suggest<'a>(name: &str, known: impl Iterator<Item = &'a str>) -> Vec<String> {
+    let name = fold(name);
+    let max_distance = (name.chars().count() / 3).max(1);
+    let mut close: Vec<_> = known
+        .map(|k| (edit_distance(&name, &fold(k)), k))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .collect();
+    close.sort();
+    close
+        .into_iter()
+        .take(MAX_SUGGESTIONS)
+        .map(|(_, k)| k.to_string())
+        .collect()
+}
+
+struct Validator<'a> {
+    school: &'a School,
+    aliases: &'a Aliases,
+    groups: &'a Groups,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Validator<'_> {
+    fn unknown<'k>(
+        &mut self,
+        kind: Kind,
+        name: &str,
+        location: &[&str],
+        known: impl Iterator<Item = &'k str>,
+    ) {
+        self.diagnostics.push(Diagnostic {
+            kind,
+            name: name.to_string(),
+            location: location.iter().map(|l| l.to_string()).collect(),
+            suggestions: suggest(name, known),
+        });
+    }
+
+    fn lab(&mut self, lab: &str, location: &[&str]) {
+        if let Pattern::Group(group) = Pattern::parse(lab) {
+            let groups = self.groups;
+            match groups.labs.get(group) {
+                Some(members) => {
+                    let location = [location, &[lab]].concat();
+                    for member in members {
+                        self.lab(member, &location);
+                    }
+                }
+                None => {
+                    let known = groups.labs.keys().map(|g| g.as_str());
+                    self.unknown(Kind::Group, lab, location, known);
+                }
+            }
+            return;
+        }
+        let key = self.school.labs.find_key(&Laboratory {
+            name: self.aliases.lab(lab),
+        });
+        if key.is_none() {
+            let known = self.school.labs().map(|l| l.name.as_str());
+            self.unknown(Kind::Lab, lab, location, known);
+        }
+    }
+
+    fn teacher(&mut self, teacher: &str, location: &[&str]) -> Option<usize> {
+        let key = self.school.teachers.find_key(&Teacher {
+            name: self.aliases.teacher(teacher),
+        });
+        if key.is_none() {
+            let known = self.school.teachers().map(|t| t.name.as_str());
+            self.unknown(Kind::Teacher, teacher, location, known);
+        }
+        key
+    }
+
+    fn grade(&mut self, grade: &str, location: &[&str]) -> Option<usize> {
+        let key = self.school.grades.find_key(&Grade {
+            name: self.aliases.grade(grade),
+        });
+        if key.is_none() {
+            let known = self.school.grades().map(|g| g.name.as_str());
+            self.unknown(Kind::Grade, grade, location, known);
+        }
+        key
+    }
+}
+
+/// The names a cap is set for, leaving out the one for every name
+fn named(caps: &BTreeMap<String, usize>) -> impl Iterator<Item = &String> {
+    caps.keys().filter(|name| *name != ANY)
+}
+
+/// Check every name in the rules against the school
+pub fn validate(school: &School, rules: &Rules) -> Vec<Diagnostic> {
+    let aliases = &rules.aliases;
+    let mut v = Validator {
+        school,
+        aliases,
+        groups: &rules.groups,
+        diagnostics: vec![],
+    };
+    let mut labs: Vec<_> = rules.forbidden_times.keys().collect();
+    labs.sort();
+    for lab in labs {
+        v.lab(lab, &[]);
+    }
+    let caps = &rules.caps;
+    for teacher in named(&caps.teacher_per_week) {
+        v.teacher(teacher, &["teacher_per_week"]);
+    }
+    for grade in named(&caps.grade_per_day) {
+        v.grade(grade, &["grade_per_day"]);
+    }
+    for lab in named(&caps.lab_per_day) {
+        v.lab(lab, &["lab_per_day"]);
+    }
+    for lab in rules.labs.keys() {
+        v.lab(lab, &["labs"]);
+    }
+    for grade in rules.sizes.keys() {
+        v.grade(grade, &["sizes"]);
+    }
+    for teacher in &rules.teachers {
+        v.teacher(&teacher.name, &[]);
+    }
+    for class in &rules.classes {
+        let subject = &class.subject;
+        let subject_id = school.subjects.find_key(&Subject {
+            name: aliases.subject(subject),
+        });
+        if subject_id.is_none() {
+            let known = school.subjects().map(|s| s.name.as_str());
+            v.unknown(Kind::Subject, subject, &[], known);
+        }
+        for teacher in &class.teachers {
+            let teacher_pattern = Pattern::parse(&teacher.name);
+            let teacher_id = match teacher_pattern {
+                Pattern::Exact(name) => v.teacher(name, &[subject]),
+                Pattern::Glob(_) => None,
+                // Only grades and labs can be grouped
+                Pattern::Group(_) => {
+                    v.unknown(Kind::Group, &teacher.name, &[subject], std::iter::empty());
+                    None
+                }
+            };
+            for grade in &teacher.grades {
+                let location = [subject.as_str(), &teacher.name, &grade.name];
+                let grade_id = match Pattern::parse(&grade.name) {
+                    Pattern::Exact(name) => v.grade(name, &location[..2]),
+                    Pattern::Glob(_) => None,
+                    Pattern::Group(group) => {
+                        match rules.groups.grades.get(group) {
+                            Some(members) => {
+                                for member in members {
+                                    v.grade(member, &location);
+                                }
+                            }
+                            None => {
+                                let known = rules.groups.grades.keys().map(|g| g.as_str());
+                                v.unknown(Kind::Group, &grade.name, &location[..2], known);
+                            }
+                        }
+                        None
+                    }
+                };
+                if let (Some(subject_id), Some(teacher_id), Some(grade_id)) =
+                    (subject_id, teacher_id, grade_id)
+                {
+                    let class_id = school.classes.find_key(&Class {
+                        teacher: teacher_id,
+                        grade: grade_id,
+                        subject: subject_id,
+                    });
+                    if class_id.is_none() {
+                        // Suggest the grades the teacher does teach the subject to
+                        let known: Vec<_> = school
+                            .classes
+                            .values()
+                            .filter(|c| c.teacher == teacher_id && c.subject == subject_id)
+                            .map(|c| school.get::<&Grade>(c.grade).name.as_str())
+                            .collect();
+                        let mut suggestions = suggest(&grade.name, known.iter().copied());
+                        if suggestions.is_empty() {
+                            suggestions = known
+                                .iter()
+                                .take(MAX_SUGGESTIONS)
+                                .map(|g| g.to_string())
+                                .collect();
+                        }
+                        v.diagnostics.push(Diagnostic {
+                            kind: Kind::Class,
+                            name: format!("{}, {subject}, {}", teacher.name, grade.name),
+                            location: location[..2].iter().map(|l| l.to_string()).collect(),
+                            suggestions,
+                        });
+                    }
+                }
+                for lab in &grade.labs {
+                    v.lab(lab, &location);
+                }
+                if let Some(second) = grade.split.as_ref().and_then(|s| s.teacher.as_ref()) {
+                    v.teacher(second, &location);
+                }
+            }
+        }
+    }
+    v.diagnostics
+}
+
+/// Check that the classes meeting every few weeks fit the weeks the schedule takes to repeat
+pub fn uneven_weeks(rules: &Rules) -> Vec<UnevenWeeks> {
+    let weeks = rules.weeks.max(1);
+    let mut uneven = vec![];
+    for class in &rules.classes {
+        for teacher in &class.teachers {
+            for grade in &teacher.grades {
+                if !weeks.is_multiple_of(grade.every) {
+                    uneven.push(UnevenWeeks {
+                        every: grade.every,
+                        weeks,
+                        location: vec![
+                            class.subject.clone(),
+                            teacher.name.clone(),
+                            grade.name.clone(),
+                        ],
+                    });
+                }
+            }
+        }
+    }
+    uneven
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::{school::Day::Monday, testing};
+
+    #[test]
+    fn reports_classes_that_dont_fit_the_weeks() {
+        let rules = testing::rules(json!({
+            "classes": [{"subject": "Química", "teachers": [{"name": "Ana", "grades": [
+                {"name": "1A", "labs": ["L1"], "every": 2},
+                {"name": "1B", "labs": ["L1"], "every": 3},
+            ]}]}],
+            "weeks": 4,
+        }));
+        let uneven = uneven_weeks(&rules);
+        assert_eq!(
+            uneven,
+            [UnevenWeeks {
+                every: 3,
+                weeks: 4,
+                location: vec!["Química".into(), "Ana".into(), "1B".into()],
+            }]
+        );
+        assert_eq!(uneven[0].fitting(), [1, 2, 4]);
+    }
+
+    #[test]
+    fn folds_accents_case_and_spaces() {
+        assert_eq!(fold(" Química\u{a0} Geral\t"), "quimica geral");
+        assert_eq!(fold("1º ANO"), fold("1o ano"));
+    }
+
+    #[test]
+    fn suggests_names_typed_differently() {
+        let known = ["Química", "Física", "Biologia"];
+        assert_eq!(suggest("quimica", known.into_iter()), ["Química"]);
+        assert_eq!(suggest("FISICA", known.into_iter()), ["Física"]);
+        assert!(suggest("Geografia", known.into_iter()).is_empty());
+    }
+
+    #[test]
+    fn suggests_a_near_miss_teacher() {
+        let school = testing::school(
+            &[
+                ("1A", "Química", "Ana Souza", Monday, "7:30"),
+                ("1B", "Química", "Bruno Lima", Monday, "8:20"),
+            ],
+            &["L1"],
+        );
+        let rules = testing::rules(json!({
+            "classes": [{"subject": "Química", "teachers": [
+                {"name": "Anna Sousa", "grades": [{"name": "1A", "labs": ["L1"]}]},
+                {"name": "Carlos", "grades": [{"name": "1B", "labs": ["L1"]}]},
+            ]}],
+        }));
+        let diagnostics = validate(&school, &rules);
+        let teachers: Vec<_> = (diagnostics.iter())
+            .filter(|d| d.kind == Kind::Teacher)
+            .map(|d| (d.name.as_str(), d.suggestions.clone()))
+            .collect();
+        assert_eq!(
+            teachers,
+            [
+                ("Anna Sousa", vec!["Ana Souza".to_string()]),
+                ("Carlos", vec![])
+            ]
+        );
+    }
+}